@@ -29,7 +29,7 @@ struct GitHubUserInfo {
 /// to specify the custom provider attributes.
 pub fn fairing() -> impl Fairing {
     OAuth2::fairing(
-        HyperSyncRustlsAdapter,
+        HyperSyncRustlsAdapter::new(),
         post_install_callback,
         "github",
         "/auth/github",
@@ -52,7 +52,7 @@ fn post_install_callback(
         .expect("parse GitHub MIME type");
     let response = client
         .get("https://api.github.com/user")
-        .header(Authorization(format!("token {}", token.access_token())))
+        .header(Authorization(token.authorization_header()))
         .header(Accept(vec![qitem(mime)]))
         .header(UserAgent("rocket_oauth2 demo application".into()))
         .send()?;
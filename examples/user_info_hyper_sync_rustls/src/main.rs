@@ -56,7 +56,7 @@ fn github_callback(
         .expect("parse GitHub MIME type");
     let response = client
         .get("https://api.github.com/user")
-        .header(Authorization(format!("token {}", token.access_token())))
+        .header(Authorization(token.authorization_header()))
         .header(Accept(vec![qitem(mime)]))
         .header(UserAgent("rocket_oauth2 demo application".into()))
         .send()?;
@@ -170,21 +170,21 @@ fn main() {
     rocket::ignite()
         .mount("/", routes![index, index_anonymous, logout])
         .attach(OAuth2::fairing(
-            HyperSyncRustlsAdapter,
+            HyperSyncRustlsAdapter::new(),
             github_callback,
             "github",
             "/auth/github",
             Some(("/login/github", vec!["user:read".to_string()])),
         ))
         .attach(OAuth2::fairing(
-            HyperSyncRustlsAdapter,
+            HyperSyncRustlsAdapter::new(),
             google_callback,
             "google",
             "/auth/google",
             Some(("/login/google", vec!["profile".to_string()])),
         ))
         .attach(OAuth2::fairing(
-            HyperSyncRustlsAdapter,
+            HyperSyncRustlsAdapter::new(),
             microsoft_callback,
             "microsoft",
             "/auth/microsoft",
@@ -0,0 +1,591 @@
+//! Functionality shared by the built-in [`Adapter`](crate::Adapter)
+//! implementations, so that they behave identically regardless of the HTTP
+//! client used.
+
+use std::convert::TryInto;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use url::form_urlencoded::{self, Serializer as UrlSerializer};
+
+use crate::request_util::grant_type;
+use crate::{
+    ClientAuthentication, DeviceAuthorization, DiscoveredProvider, Error, ErrorCode, ErrorKind,
+    Introspection, JwsAlgorithm, OAuthConfig, TokenRequest, TokenResponse, TokenTypeHint,
+};
+
+/// The default `User-Agent` sent with requests.
+#[cfg(feature = "hyper_sync_rustls_adapter")]
+pub(crate) const DEFAULT_USER_AGENT: &str = concat!("rocket_oauth2/", env!("CARGO_PKG_VERSION"));
+
+/// The maximum size of a response body that will be read from the server.
+const MAX_RESPONSE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// The form-encoded body of a request to the token or revocation endpoint,
+/// and the value of the `Authorization` header to send along with it, if any.
+pub(crate) struct TokenRequestBody {
+    pub(crate) body: String,
+    pub(crate) authorization: Option<String>,
+}
+
+/// A form-encoded request body whose standard parameter names are mapped with
+/// [`OAuthConfig::token_param_name`].
+struct TokenForm<'a> {
+    config: &'a OAuthConfig,
+    ser: UrlSerializer<'static, String>,
+}
+
+impl<'a> TokenForm<'a> {
+    fn new(config: &'a OAuthConfig) -> TokenForm<'a> {
+        TokenForm {
+            config,
+            ser: UrlSerializer::new(String::new()),
+        }
+    }
+
+    fn append_pair(&mut self, name: &str, value: &str) {
+        self.ser
+            .append_pair(self.config.token_param_name(name), value);
+    }
+
+    fn finish(mut self) -> String {
+        self.ser.finish()
+    }
+}
+
+/// Build the body of a token request as described by RFC 6749 §4.1.3 and
+/// §6, authenticating the client as described by RFC 6749 §2.3.1.
+pub(crate) fn token_request_body(
+    config: &OAuthConfig,
+    token: TokenRequest,
+) -> Result<TokenRequestBody, Error> {
+    let mut ser = TokenForm::new(config);
+    ser.append_pair("grant_type", config.grant_type(grant_type(&token)));
+    match token {
+        TokenRequest::AuthorizationCode {
+            code,
+            code_verifier,
+        } => {
+            ser.append_pair("code", &code);
+            ser.append_pair("redirect_uri", config.redirect_uri());
+            if let Some(code_verifier) = code_verifier {
+                ser.append_pair("code_verifier", &code_verifier);
+            }
+        }
+        TokenRequest::RefreshToken(token) => {
+            ser.append_pair("refresh_token", &token);
+        }
+        TokenRequest::DeviceCode(device_code) => {
+            ser.append_pair("device_code", &device_code);
+        }
+        TokenRequest::ClientCredentials { scopes } => {
+            if !scopes.is_empty() {
+                ser.append_pair("scope", &scopes.join(" "));
+            }
+        }
+        #[cfg(feature = "deprecated_password_grant")]
+        TokenRequest::Password {
+            username,
+            password,
+            scopes,
+        } => {
+            ser.append_pair("username", &username);
+            ser.append_pair("password", &password);
+            if !scopes.is_empty() {
+                ser.append_pair("scope", &scopes.join(" "));
+            }
+        }
+    }
+    for resource in config.resources() {
+        ser.append_pair("resource", resource);
+    }
+
+    let authorization = authenticate_client(config, &mut ser)?;
+    Ok(TokenRequestBody {
+        body: ser.finish(),
+        authorization,
+    })
+}
+
+/// Get the host of `uri` for logging, without any credentials, path, or
+/// query which might contain secrets.
+#[cfg(all(feature = "tracing", feature = "hyper_sync_rustls_adapter"))]
+pub(crate) fn log_host(uri: &str) -> String {
+    url::Url::parse(uri)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_default()
+}
+
+/// Build the body of a token revocation request as described by RFC 7009
+/// §2.1.
+///
+/// Returns an error of kind `Unsupported` if there is no revocation endpoint.
+pub(crate) fn revocation_request_body(
+    config: &OAuthConfig,
+    token: &str,
+    token_type_hint: Option<TokenTypeHint>,
+) -> Result<(String, TokenRequestBody), Error> {
+    let uri = config
+        .revocation_uri()
+        .ok_or_else(|| unsupported("no revocation_uri is configured"))?;
+    Ok((
+        uri.into_owned(),
+        token_management_body(config, token, token_type_hint)?,
+    ))
+}
+
+/// Build the body of a token introspection request as described by RFC 7662
+/// §2.1.
+///
+/// Returns an error of kind `Unsupported` if there is no introspection
+/// endpoint.
+pub(crate) fn introspection_request_body(
+    config: &OAuthConfig,
+    token: &str,
+) -> Result<(String, TokenRequestBody), Error> {
+    let uri = config
+        .introspection_uri()
+        .ok_or_else(|| unsupported("no introspection_uri is configured"))?;
+    Ok((
+        uri.into_owned(),
+        token_management_body(config, token, None)?,
+    ))
+}
+
+/// Build the body of a device authorization request as described by RFC 8628
+/// §3.1.
+///
+/// Returns an error of kind `Unsupported` if there is no device authorization
+/// endpoint.
+pub(crate) fn device_authorization_request_body(
+    config: &OAuthConfig,
+    scopes: &[&str],
+) -> Result<(String, TokenRequestBody), Error> {
+    let uri = config
+        .device_authorization_uri()
+        .ok_or_else(|| unsupported("no device_authorization_uri is configured"))?;
+
+    let mut ser = TokenForm::new(config);
+    if !scopes.is_empty() {
+        ser.append_pair("scope", &scopes.join(" "));
+    }
+
+    let authorization = authenticate_client(config, &mut ser)?;
+    let body = TokenRequestBody {
+        body: ser.finish(),
+        authorization,
+    };
+    Ok((uri.into_owned(), body))
+}
+
+/// Build the body of a revocation or introspection request, which share the
+/// same parameters.
+fn token_management_body(
+    config: &OAuthConfig,
+    token: &str,
+    token_type_hint: Option<TokenTypeHint>,
+) -> Result<TokenRequestBody, Error> {
+    let mut ser = TokenForm::new(config);
+    ser.append_pair("token", token);
+    if let Some(hint) = token_type_hint {
+        ser.append_pair("token_type_hint", hint.as_str());
+    }
+
+    let authorization = authenticate_client(config, &mut ser)?;
+    Ok(TokenRequestBody {
+        body: ser.finish(),
+        authorization,
+    })
+}
+
+fn unsupported(reason: &str) -> Error {
+    Error::new_from(ErrorKind::Unsupported, String::from(reason))
+}
+
+/// Interpret the response to a token revocation request, given its status
+/// code and body.
+pub(crate) fn revocation_response(status: u16, body: impl Read) -> Result<(), Error> {
+    if !(200..300).contains(&status) {
+        let mut buf = vec![];
+        let _ = body.take(MAX_RESPONSE_SIZE).read_to_end(&mut buf);
+        return Err(exchange_error(status, &buf));
+    }
+    Ok(())
+}
+
+/// Interpret the response to a token introspection request, given its status
+/// code and body.
+pub(crate) fn introspection_response(status: u16, body: impl Read) -> Result<Introspection, Error> {
+    json_response(status, body)?.try_into()
+}
+
+/// Interpret the response to a device authorization request, given its
+/// status code and body.
+pub(crate) fn device_authorization_response(
+    status: u16,
+    body: impl Read,
+) -> Result<DeviceAuthorization, Error> {
+    json_response(status, body)?.try_into()
+}
+
+/// Authenticate the client as described by RFC 6749 §2.3.1, either by adding
+/// the credentials to `ser` or by returning an `Authorization` header value.
+fn authenticate_client(
+    config: &OAuthConfig,
+    ser: &mut TokenForm<'_>,
+) -> Result<Option<String>, Error> {
+    let mut authorization = None;
+    match config.client_authentication() {
+        ClientAuthentication::PrivateKeyJwt { key, alg, kid } => {
+            let assertion = client_assertion(config, key, *alg, kid.as_deref())?;
+            ser.append_pair("client_id", config.client_id());
+            ser.append_pair(
+                "client_assertion_type",
+                "urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+            );
+            ser.append_pair("client_assertion", &assertion);
+        }
+        // Public clients have no secret, and only identify themselves
+        // (RFC 6749 §4.1.3).
+        _ if config.client_secret().is_empty() => {
+            ser.append_pair("client_id", config.client_id());
+        }
+        ClientAuthentication::RequestBody => {
+            ser.append_pair("client_id", config.client_id());
+            ser.append_pair("client_secret", config.client_secret());
+        }
+        ClientAuthentication::BasicHeader => {
+            // RFC 6749 §2.3.1 requires the credentials to be form-encoded
+            // before they are combined.
+            let credentials = format!(
+                "{}:{}",
+                form_urlencoded::byte_serialize(config.client_id().as_bytes()).collect::<String>(),
+                form_urlencoded::byte_serialize(config.client_secret().as_bytes())
+                    .collect::<String>(),
+            );
+            authorization = Some(format!("Basic {}", base64::encode(&credentials)));
+        }
+    }
+    Ok(authorization)
+}
+
+/// How long a JWT client assertion is valid for.
+const CLIENT_ASSERTION_LIFETIME: u64 = 5 * 60;
+
+/// Build a JWT client assertion as described by RFC 7523 §3, signed with
+/// `key`.
+fn client_assertion(
+    config: &OAuthConfig,
+    key: &[u8],
+    alg: JwsAlgorithm,
+    kid: Option<&str>,
+) -> Result<String, Error> {
+    let mut jti = [0; 16];
+    SystemRandom::new().fill(&mut jti).map_err(|_| {
+        Error::new_from(
+            ErrorKind::Other,
+            String::from("failed to generate random data"),
+        )
+    })?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut header = json!({ "alg": alg.as_str(), "typ": "JWT" });
+    if let Some(kid) = kid {
+        header["kid"] = Value::from(kid);
+    }
+    let claims = json!({
+        "iss": config.client_id(),
+        "sub": config.client_id(),
+        "aud": config.token_uri(),
+        "jti": base64::encode_config(&jti, base64::URL_SAFE_NO_PAD),
+        "iat": now,
+        "exp": now + CLIENT_ASSERTION_LIFETIME,
+    });
+
+    let message = format!(
+        "{}.{}",
+        base64::encode_config(&header.to_string(), base64::URL_SAFE_NO_PAD),
+        base64::encode_config(&claims.to_string(), base64::URL_SAFE_NO_PAD),
+    );
+    let signature = alg.sign(key, message.as_bytes())?;
+    Ok(format!(
+        "{}.{}",
+        message,
+        base64::encode_config(&signature, base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+/// Interpret the response to a token request, given its status code,
+/// `Content-Type`, `Date`, and body.
+///
+/// Some providers ignore the `Accept` header and respond with a
+/// form-encoded body, which is converted to the equivalent JSON object.
+///
+/// The token lifetime is counted from the `Date` header if it is present,
+/// valid, and slightly earlier than the local time; see
+/// [`TokenResponse::expires_at`].
+pub(crate) fn token_response(
+    status: u16,
+    content_type: Option<&str>,
+    date: Option<&str>,
+    body: impl Read,
+) -> Result<TokenResponse, Error> {
+    let response = token_response_body(status, content_type, body)?;
+    match date.and_then(parse_http_date) {
+        Some(date) => Ok(response.with_date(date)),
+        None => Ok(response),
+    }
+}
+
+fn token_response_body(
+    status: u16,
+    content_type: Option<&str>,
+    body: impl Read,
+) -> Result<TokenResponse, Error> {
+    let mime = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase());
+    let is_form = mime.as_deref() == Some("application/x-www-form-urlencoded");
+    if !is_form || !(200..300).contains(&status) {
+        return match json_response(status, body) {
+            Ok(data) => data.try_into(),
+            // Some providers send JSON with another content type, so it is
+            // only blamed if the body could not be parsed.
+            Err(e) if *e.kind() == ErrorKind::ExchangeFailure && !is_json_type(mime.as_deref()) => {
+                Err(Error::new_from(
+                    ErrorKind::UnexpectedContentType {
+                        content_type: content_type.unwrap_or_default().to_string(),
+                    },
+                    e,
+                ))
+            }
+            Err(e) => Err(e),
+        };
+    }
+
+    let mut buf = vec![];
+    body.take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut buf)
+        .map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
+
+    let mut data = serde_json::Map::new();
+    for (name, value) in form_urlencoded::parse(&buf) {
+        // `expires_in` is the only standard member which is not a string.
+        let value = match (name.as_ref(), value.parse::<i64>()) {
+            ("expires_in", Ok(expires_in)) => Value::from(expires_in),
+            _ => Value::String(value.into_owned()),
+        };
+        data.insert(name.into_owned(), value);
+    }
+    Value::Object(data).try_into()
+}
+
+/// Returns whether `mime` is a JSON media type, or unknown.
+fn is_json_type(mime: Option<&str>) -> bool {
+    match mime {
+        Some(mime) => mime == "application/json" || mime.ends_with("+json"),
+        None => true,
+    }
+}
+
+/// Read a JSON response body, returning an error if the status code
+/// indicates failure or the body is not valid JSON.
+fn json_response(status: u16, body: impl Read) -> Result<Value, Error> {
+    let mut buf = vec![];
+    let read = body.take(MAX_RESPONSE_SIZE).read_to_end(&mut buf);
+
+    if !(200..300).contains(&status) {
+        // The error details are optional; failing to read them is not
+        // worth reporting over the status code itself.
+        return Err(exchange_error(status, &buf));
+    }
+
+    read.map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
+    serde_json::from_slice(&buf).map_err(|e| {
+        Error::new_from(
+            ErrorKind::ExchangeFailure,
+            InvalidResponse {
+                status,
+                snippet: body_snippet(&buf),
+                source: e,
+            },
+        )
+    })
+}
+
+/// The number of characters of an invalid response body to include in
+/// errors.
+const SNIPPET_LENGTH: usize = 200;
+
+/// A successful response whose body could not be parsed as JSON, such as an
+/// HTML error page from a proxy.
+#[derive(Debug)]
+struct InvalidResponse {
+    status: u16,
+    snippet: String,
+    source: serde_json::Error,
+}
+
+impl fmt::Display for InvalidResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid JSON in response with status code {} ({}), body: {:?}",
+            self.status, self.source, self.snippet
+        )
+    }
+}
+
+impl StdError for InvalidResponse {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Get the start of `body` for inclusion in an error. A body which appears to
+/// contain a token (for example, a form-encoded token response) is omitted.
+fn body_snippet(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    if body.contains("access_token") || body.contains("refresh_token") {
+        return String::from("<omitted: may contain a token>");
+    }
+
+    let mut snippet: String = body.chars().take(SNIPPET_LENGTH).collect();
+    if snippet.len() < body.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+/// Returns an error of kind [`ErrorKind::RateLimited`] if the server
+/// responded with `429 Too Many Requests` and a valid `Retry-After` header.
+pub(crate) fn check_rate_limit(status: u16, retry_after: Option<&str>) -> Result<(), Error> {
+    match (status, retry_after.and_then(parse_retry_after)) {
+        (429, Some(retry_after)) => Err(Error::new(ErrorKind::RateLimited { retry_after })),
+        _ => Ok(()),
+    }
+}
+
+/// Parse the value of a `Retry-After` header, which is either a number of
+/// seconds or an HTTP-date (RFC 7231 §7.1.3). Dates in the past are treated
+/// as no delay.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = parse_http_date(value)?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::from_secs(0)),
+    )
+}
+
+/// Parse an HTTP-date in the preferred format of RFC 7231 §7.1.1.1, such as
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn parse_http_date(value: &str) -> Option<SystemTime> {
+    let date = time::strptime(value.trim(), "%a, %d %b %Y %H:%M:%S GMT").ok()?;
+    let secs = date.to_timespec().sec;
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// Create a new `Error` for a token exchange which failed with the given
+/// `status`, parsing the error details from `body` if it is a JSON error
+/// response as described in RFC 6749 §5.2.
+fn exchange_error(status: u16, body: &[u8]) -> Error {
+    let data: Option<Value> = serde_json::from_slice(body).ok();
+    let field = |name: &str| {
+        data.as_ref()
+            .and_then(|d| d.get(name))
+            .and_then(Value::as_str)
+            .map(String::from)
+    };
+
+    Error::new(ErrorKind::ExchangeError {
+        status,
+        error: field("error").as_deref().map(ErrorCode::from),
+        description: field("error_description"),
+        uri: field("error_uri"),
+    })
+}
+
+/// Get the URI of the OpenID Connect discovery document for `issuer`, as
+/// described by OpenID Connect Discovery 1.0 §4.
+pub(crate) fn discovery_uri(issuer: &str) -> String {
+    format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    )
+}
+
+/// Interpret the response to a request for a JSON Web Key Set, given its
+/// status code and body.
+///
+/// The key set must be an object with a `keys` array, as described in RFC
+/// 7517 §5.
+pub(crate) fn jwks_response(status: u16, body: impl Read) -> Result<Value, Error> {
+    if !(200..300).contains(&status) {
+        return Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            format!("JWKS request returned non-success status code: {}", status),
+        ));
+    }
+
+    let data: Value = serde_json::from_reader(body.take(MAX_RESPONSE_SIZE))
+        .map_err(|e| Error::new_from(ErrorKind::DiscoveryFailure, e))?;
+    if !data.get("keys").is_some_and(Value::is_array) {
+        return Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            String::from("JWKS did not contain a 'keys' array"),
+        ));
+    }
+
+    Ok(data)
+}
+
+/// Interpret the response to a discovery request for `issuer`, given its
+/// status code and body.
+///
+/// The issuer in the document must match `issuer`, as required by OpenID
+/// Connect Discovery 1.0 §4.3.
+pub(crate) fn discovery_response(
+    issuer: &str,
+    status: u16,
+    body: impl Read,
+) -> Result<DiscoveredProvider, Error> {
+    if !(200..300).contains(&status) {
+        return Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            format!("discovery returned non-success status code: {}", status),
+        ));
+    }
+
+    let data: Value = serde_json::from_reader(body.take(MAX_RESPONSE_SIZE))
+        .map_err(|e| Error::new_from(ErrorKind::DiscoveryFailure, e))?;
+    let provider: DiscoveredProvider = data.try_into()?;
+
+    if provider.issuer().trim_end_matches('/') != issuer.trim_end_matches('/') {
+        return Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            format!(
+                "discovery document issuer '{}' does not match '{}'",
+                provider.issuer(),
+                issuer
+            ),
+        ));
+    }
+
+    Ok(provider)
+}
@@ -1,16 +1,244 @@
+use std::borrow::Cow;
 use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
+use ring::rand::SystemRandom;
+use ring::signature;
 use rocket::config::{self, Config, ConfigError, Table, Value};
+use rocket::http::SameSite;
 
-use crate::{Provider, StaticProvider};
+use crate::{
+    Adapter, Error, ErrorKind, FlowEvent, Provider, RandomStateGenerator, StateGenerator,
+    StaticProvider, TokenResponse,
+};
+
+type RefreshHook = Arc<dyn Fn(&TokenResponse) + Send + Sync>;
+type EventHook = Arc<dyn Fn(&FlowEvent) + Send + Sync>;
+
+const DEFAULT_STATE_COOKIE_NAME: &str = "rocket_oauth2_state";
+const DEFAULT_STATE_COOKIE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// The method used to derive a PKCE `code_challenge` from a `code_verifier`,
+/// as described in RFC 7636 §4.2.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum PkceMethod {
+    /// The challenge is the base64url-encoded SHA-256 hash of the verifier.
+    S256,
+    /// The challenge is the verifier itself. This should only be used when
+    /// the service provider does not support `S256`.
+    Plain,
+}
+
+impl PkceMethod {
+    /// Returns the value of the `code_challenge_method` parameter for this
+    /// method.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PkceMethod::S256 => "S256",
+            PkceMethod::Plain => "plain",
+        }
+    }
+}
+
+/// The method used to authenticate the client to the token endpoint, as
+/// described in RFC 6749 §2.3.1.
+#[derive(Clone, Eq, PartialEq, Hash, Default)]
+pub enum ClientAuthentication {
+    /// The `client_id` and `client_secret` are sent in the request body
+    /// (`client_secret_post`). This is the default.
+    #[default]
+    RequestBody,
+    /// The `client_id` and `client_secret` are sent in an `Authorization`
+    /// header using HTTP Basic authentication (`client_secret_basic`).
+    BasicHeader,
+    /// The client sends a JWT assertion signed with its private key
+    /// (`private_key_jwt`), as described in OpenID Connect Core §9 and
+    /// RFC 7523. The `client_secret` is not used.
+    ///
+    /// The assertion is issued and signed by the `client_id`, its audience is
+    /// the token endpoint, and it is valid for five minutes.
+    PrivateKeyJwt {
+        /// The private key, as a DER-encoded PKCS#8 document. A PEM key can
+        /// be converted with
+        /// `openssl pkcs8 -topk8 -nocrypt -outform DER -in key.pem -out key.der`.
+        key: Vec<u8>,
+        /// The signature algorithm, which must match the type of `key`.
+        alg: JwsAlgorithm,
+        /// The key ID sent in the `kid` header of the assertion, if any.
+        kid: Option<String>,
+    },
+}
+
+impl fmt::Debug for ClientAuthentication {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientAuthentication::RequestBody => f.write_str("RequestBody"),
+            ClientAuthentication::BasicHeader => f.write_str("BasicHeader"),
+            ClientAuthentication::PrivateKeyJwt { alg, kid, .. } => f
+                .debug_struct("PrivateKeyJwt")
+                .field("key", &(..))
+                .field("alg", alg)
+                .field("kid", kid)
+                .finish(),
+        }
+    }
+}
+
+/// The algorithm used to sign a JWT client assertion, as described in
+/// RFC 7518 §3.1.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum JwsAlgorithm {
+    /// RSASSA-PKCS1-v1_5 using SHA-256, with an RSA key.
+    RS256,
+    /// ECDSA using P-256 and SHA-256, with a P-256 key.
+    ES256,
+}
+
+impl JwsAlgorithm {
+    /// Returns the value of the `alg` header for this algorithm.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JwsAlgorithm::RS256 => "RS256",
+            JwsAlgorithm::ES256 => "ES256",
+        }
+    }
+
+    /// Sign `message` with the PKCS#8 `key`.
+    pub(crate) fn sign(self, key: &[u8], message: &[u8]) -> Result<Vec<u8>, Error> {
+        let invalid_key = |_| {
+            Error::new_from(
+                ErrorKind::Other,
+                format!("the private key is not a valid {} key", self.as_str()),
+            )
+        };
+        let failed = |_| Error::new_from(ErrorKind::Other, String::from("signing failed"));
+        let rng = SystemRandom::new();
+        let key = untrusted::Input::from(key);
+
+        match self {
+            JwsAlgorithm::RS256 => {
+                let key_pair = signature::RSAKeyPair::from_pkcs8(key).map_err(invalid_key)?;
+                let mut sig = vec![0; key_pair.public_modulus_len()];
+                let mut state =
+                    signature::RSASigningState::new(Arc::new(key_pair)).map_err(failed)?;
+                state
+                    .sign(&signature::RSA_PKCS1_SHA256, &rng, message, &mut sig)
+                    .map_err(failed)?;
+                Ok(sig)
+            }
+            JwsAlgorithm::ES256 => {
+                let key_pair = signature::ECDSAKeyPair::from_pkcs8(
+                    &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                    key,
+                )
+                .map_err(invalid_key)?;
+                let sig = key_pair
+                    .sign(untrusted::Input::from(message), &rng)
+                    .map_err(failed)?;
+                Ok(sig.as_ref().to_vec())
+            }
+        }
+    }
+}
+
+/// How the state cookie, which holds the state and the other data of an
+/// authorization in progress, is protected.
+#[derive(Clone, Eq, PartialEq, Hash, Default)]
+pub enum StateCookieProtection {
+    /// The cookie is encrypted and authenticated with Rocket's secret key, as
+    /// a private cookie. This is the default.
+    #[default]
+    Private,
+    /// The cookie is authenticated with HMAC-SHA256 using the given key, of
+    /// at least 32 bytes, but not encrypted. The user agent can read the PKCE
+    /// code verifier and OpenID Connect nonce, but cannot change them.
+    Signed(Vec<u8>),
+    /// The cookie is neither encrypted nor authenticated. This is only safe
+    /// with a [`StateGenerator`] which validates the state against a
+    /// server-side store, and the `return_to` value must be validated as
+    /// usual.
+    Plain,
+}
+
+impl fmt::Debug for StateCookieProtection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateCookieProtection::Private => f.write_str("Private"),
+            StateCookieProtection::Signed(_) => f.debug_tuple("Signed").field(&(..)).finish(),
+            StateCookieProtection::Plain => f.write_str("Plain"),
+        }
+    }
+}
+
+/// The minimum length of the key of [`StateCookieProtection::Signed`].
+const MIN_STATE_COOKIE_KEY_LEN: usize = 32;
+
+/// How the service provider returns the authorization response to the
+/// redirect URI, as described in OAuth 2.0 Multiple Response Type Encoding
+/// Practices §2.1 and OAuth 2.0 Form Post Response Mode.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub enum ResponseMode {
+    /// The response is sent in the query string of a `GET` request. This is
+    /// the default, and no `response_mode` parameter is sent.
+    #[default]
+    Query,
+    /// The response is sent as form fields in the body of a `POST` request.
+    ///
+    /// The `POST` is a cross-site request, so browsers will only send the
+    /// state cookie with it if the cookie has `SameSite=None`. See
+    /// [`OAuthConfig::with_state_cookie_same_site`] for the limitations of
+    /// Rocket 0.4 in this regard.
+    FormPost,
+}
+
+impl ResponseMode {
+    /// Returns the value of the `response_mode` parameter for this mode.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResponseMode::Query => "query",
+            ResponseMode::FormPost => "form_post",
+        }
+    }
+}
 
 /// Holds configuration for an OAuth application. This consists of the [Provider]
 /// details, a `client_id` and `client_secret`, and a `redirect_uri`.
+#[derive(Clone)]
 pub struct OAuthConfig {
-    provider: Box<dyn Provider>,
+    provider: Arc<dyn Provider>,
     client_id: String,
     client_secret: String,
     redirect_uri: String,
+    pkce: Option<PkceMethod>,
+    client_authentication: ClientAuthentication,
+    extra_params: Vec<(String, String)>,
+    token_param_names: Vec<(String, String)>,
+    grant_types: Vec<(String, String)>,
+    resources: Vec<String>,
+    default_scopes: Vec<String>,
+    scope_delimiter: String,
+    include_empty_scope: bool,
+    issuer: Option<String>,
+    userinfo_uri: Option<String>,
+    jwks_uri: Option<String>,
+    revocation_uri: Option<String>,
+    introspection_uri: Option<String>,
+    device_authorization_uri: Option<String>,
+    state_cookie_same_site: SameSite,
+    state_cookie_secure: bool,
+    state_cookie_name: String,
+    state_cookie_max_age: Duration,
+    state_cookie_protection: StateCookieProtection,
+    state_generator: Arc<dyn StateGenerator>,
+    on_token_refreshed: Option<RefreshHook>,
+    on_event: Option<EventHook>,
+    response_mode: ResponseMode,
+    response_type: String,
+    audience: Option<String>,
+    forwarded_redirect_uri: bool,
+    redirect_base: Option<String>,
+    expiry_skew: Duration,
 }
 
 impl fmt::Debug for OAuthConfig {
@@ -20,6 +248,38 @@ impl fmt::Debug for OAuthConfig {
             .field("client_id", &self.client_id)
             .field("client_secret", &self.client_secret)
             .field("redirect_uri", &self.redirect_uri)
+            .field("pkce", &self.pkce)
+            .field("client_authentication", &self.client_authentication)
+            .field("extra_params", &self.extra_params)
+            .field("token_param_names", &self.token_param_names)
+            .field("grant_types", &self.grant_types)
+            .field("resources", &self.resources)
+            .field("default_scopes", &self.default_scopes)
+            .field("scope_delimiter", &self.scope_delimiter)
+            .field("include_empty_scope", &self.include_empty_scope)
+            .field("issuer", &self.issuer)
+            .field("userinfo_uri", &self.userinfo_uri)
+            .field("jwks_uri", &self.jwks_uri)
+            .field("revocation_uri", &self.revocation_uri)
+            .field("introspection_uri", &self.introspection_uri)
+            .field("device_authorization_uri", &self.device_authorization_uri)
+            .field("state_cookie_same_site", &self.state_cookie_same_site)
+            .field("state_cookie_secure", &self.state_cookie_secure)
+            .field("state_cookie_name", &self.state_cookie_name)
+            .field("state_cookie_max_age", &self.state_cookie_max_age)
+            .field("state_cookie_protection", &self.state_cookie_protection)
+            .field("state_generator", &(..))
+            .field(
+                "on_token_refreshed",
+                &self.on_token_refreshed.as_ref().map(|_| ..),
+            )
+            .field("on_event", &self.on_event.as_ref().map(|_| ..))
+            .field("response_mode", &self.response_mode)
+            .field("response_type", &self.response_type)
+            .field("audience", &self.audience)
+            .field("forwarded_redirect_uri", &self.forwarded_redirect_uri)
+            .field("redirect_base", &self.redirect_base)
+            .field("expiry_skew", &self.expiry_skew)
             .finish()
     }
 }
@@ -38,6 +298,10 @@ fn get_config_string(table: &Table, key: &str) -> config::Result<String> {
 
 impl OAuthConfig {
     /// Create a new OAuthConfig.
+    ///
+    /// For a public client, such as a native application, `client_secret`
+    /// may be empty. The client then only sends its `client_id` to the token
+    /// endpoint, and should use PKCE (see [`OAuthConfig::with_pkce`]).
     pub fn new(
         provider: impl Provider,
         client_id: String,
@@ -45,13 +309,409 @@ impl OAuthConfig {
         redirect_uri: String,
     ) -> OAuthConfig {
         OAuthConfig {
-            provider: Box::new(provider),
+            provider: Arc::new(provider),
             client_id,
             client_secret,
             redirect_uri,
+            pkce: None,
+            client_authentication: ClientAuthentication::default(),
+            extra_params: vec![],
+            token_param_names: vec![],
+            grant_types: vec![],
+            resources: vec![],
+            default_scopes: vec![],
+            scope_delimiter: String::from(" "),
+            include_empty_scope: false,
+            issuer: None,
+            userinfo_uri: None,
+            jwks_uri: None,
+            revocation_uri: None,
+            introspection_uri: None,
+            device_authorization_uri: None,
+            state_cookie_same_site: SameSite::Lax,
+            state_cookie_secure: true,
+            state_cookie_name: String::from(DEFAULT_STATE_COOKIE_NAME),
+            state_cookie_max_age: DEFAULT_STATE_COOKIE_MAX_AGE,
+            state_cookie_protection: StateCookieProtection::Private,
+            state_generator: Arc::new(RandomStateGenerator),
+            on_token_refreshed: None,
+            on_event: None,
+            response_mode: ResponseMode::default(),
+            response_type: String::from("code"),
+            audience: None,
+            forwarded_redirect_uri: false,
+            redirect_base: None,
+            expiry_skew: Duration::from_secs(0),
         }
     }
 
+    /// Create a new OAuthConfig for the OpenID Connect provider identified
+    /// by `issuer`, using `adapter` to fetch its discovery document.
+    ///
+    /// The discovery document is only fetched once, here; the discovered
+    /// endpoints are kept for the lifetime of the returned configuration.
+    ///
+    /// ```rust,no_run
+    /// # extern crate rocket_oauth2;
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    /// use rocket_oauth2::OAuthConfig;
+    ///
+    /// # fn build() -> Result<(), rocket_oauth2::Error> {
+    /// let config = OAuthConfig::from_issuer(
+    ///     &HyperSyncRustlsAdapter::new(),
+    ///     "https://accounts.google.com",
+    ///     "client id".to_string(),
+    ///     "client secret".to_string(),
+    ///     "http://localhost:8000/auth/google".to_string(),
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_issuer(
+        adapter: &dyn Adapter,
+        issuer: &str,
+        client_id: String,
+        client_secret: String,
+        redirect_uri: String,
+    ) -> Result<OAuthConfig, Error> {
+        let provider = adapter.discover(issuer)?;
+        Ok(OAuthConfig::new(
+            provider,
+            client_id,
+            client_secret,
+            redirect_uri,
+        ))
+    }
+
+    /// Sets the redirect URI, replacing the one given to [`OAuthConfig::new`].
+    pub fn with_redirect_uri(mut self, redirect_uri: String) -> OAuthConfig {
+        self.redirect_uri = redirect_uri;
+        self
+    }
+
+    /// Enables PKCE (RFC 7636) for this configuration using the given
+    /// challenge method.
+    pub fn with_pkce(mut self, method: PkceMethod) -> OAuthConfig {
+        self.pkce = Some(method);
+        self
+    }
+
+    /// Sets the method used to authenticate the client to the token endpoint.
+    pub fn with_client_authentication(mut self, method: ClientAuthentication) -> OAuthConfig {
+        self.client_authentication = method;
+        self
+    }
+
+    /// Adds a query parameter to be included in every authorization URI
+    /// generated with this configuration, such as Google's `access_type`.
+    pub fn with_extra_param(mut self, name: String, value: String) -> OAuthConfig {
+        self.extra_params.push((name, value));
+        self
+    }
+
+    /// Adds a scope which is requested in every authorization request, before
+    /// the scopes given for the request. Scopes requested more than once are
+    /// only sent once.
+    pub fn with_default_scope(mut self, scope: String) -> OAuthConfig {
+        self.default_scopes.push(scope);
+        self
+    }
+
+    /// Adds a resource indicator (RFC 8707), the URI of a resource server
+    /// which the access token is requested for. A `resource` parameter is
+    /// sent for each one in the authorization URI and in token requests.
+    pub fn with_resource(mut self, resource: String) -> OAuthConfig {
+        self.resources.push(resource);
+        self
+    }
+
+    /// Renames the standard parameter `name`, such as `client_id`, to
+    /// `renamed` in the bodies of requests to the token endpoint and the
+    /// other endpoints which authenticate the client, for service providers
+    /// which do not use the names given by the specifications.
+    pub fn with_token_param_name(mut self, name: String, renamed: String) -> OAuthConfig {
+        self.token_param_names.retain(|(n, _)| *n != name);
+        self.token_param_names.push((name, renamed));
+        self
+    }
+
+    /// Sends `custom` instead of the standard `grant_type`, such as
+    /// `authorization_code` or `refresh_token`, in requests to the token
+    /// endpoint, for service providers which use vendor-specific grant types.
+    pub fn with_grant_type(mut self, grant_type: String, custom: String) -> OAuthConfig {
+        self.grant_types.retain(|(g, _)| *g != grant_type);
+        self.grant_types.push((grant_type, custom));
+        self
+    }
+
+    /// Sets the delimiter used to join scopes in the authorization URI. The
+    /// default is a space, as required by RFC 6749 §3.3, but some service
+    /// providers expect a comma instead.
+    pub fn with_scope_delimiter(mut self, delimiter: String) -> OAuthConfig {
+        self.scope_delimiter = delimiter;
+        self
+    }
+
+    /// Sets whether an empty `scope` parameter is included in the
+    /// authorization URI when no scopes are requested. The default is
+    /// `false`, which omits the parameter, but some service providers reject
+    /// authorization requests without one.
+    pub fn with_include_empty_scope(mut self, include: bool) -> OAuthConfig {
+        self.include_empty_scope = include;
+        self
+    }
+
+    /// Sets the OpenID Connect UserInfo endpoint URI, overriding the one given
+    /// by the [Provider], if any.
+    pub fn with_userinfo_uri(mut self, uri: String) -> OAuthConfig {
+        self.userinfo_uri = Some(uri);
+        self
+    }
+
+    /// Sets the OpenID Connect issuer identifier, overriding the one given by
+    /// the [Provider], if any.
+    pub fn with_issuer(mut self, issuer: String) -> OAuthConfig {
+        self.issuer = Some(issuer);
+        self
+    }
+
+    /// Sets the JSON Web Key Set URI, overriding the one given by the
+    /// [Provider], if any.
+    pub fn with_jwks_uri(mut self, uri: String) -> OAuthConfig {
+        self.jwks_uri = Some(uri);
+        self
+    }
+
+    /// Sets the token revocation URI (RFC 7009), overriding the one given by
+    /// the [Provider], if any.
+    pub fn with_revocation_uri(mut self, uri: String) -> OAuthConfig {
+        self.revocation_uri = Some(uri);
+        self
+    }
+
+    /// Sets the token introspection URI (RFC 7662), overriding the one given
+    /// by the [Provider], if any.
+    pub fn with_introspection_uri(mut self, uri: String) -> OAuthConfig {
+        self.introspection_uri = Some(uri);
+        self
+    }
+
+    /// Sets the device authorization URI (RFC 8628), overriding the one
+    /// given by the [Provider], if any.
+    pub fn with_device_authorization_uri(mut self, uri: String) -> OAuthConfig {
+        self.device_authorization_uri = Some(uri);
+        self
+    }
+
+    /// Sets the `SameSite` attribute of the state cookie. The default is
+    /// `Lax`, which allows the cookie to be sent when the service provider
+    /// redirects back to the application with a top-level `GET` navigation.
+    ///
+    /// Note that the version of the `cookie` crate used by Rocket 0.4 omits
+    /// the attribute entirely for `SameSite::None`, rather than sending
+    /// `SameSite=None`. Browsers which treat a missing attribute as `Lax` will
+    /// therefore not send the cookie with a cross-site `POST` callback.
+    pub fn with_state_cookie_same_site(mut self, same_site: SameSite) -> OAuthConfig {
+        self.state_cookie_same_site = same_site;
+        self
+    }
+
+    /// Sets whether the state cookie has the `Secure` attribute. The default
+    /// is `true`; it can be disabled for local development over plain HTTP.
+    pub fn with_state_cookie_secure(mut self, secure: bool) -> OAuthConfig {
+        self.state_cookie_secure = secure;
+        self
+    }
+
+    /// Sets whether the redirect URI is derived from the `X-Forwarded-Proto`,
+    /// `X-Forwarded-Host`, and `X-Forwarded-Prefix` headers of each request,
+    /// for applications served behind a reverse proxy which rewrites the
+    /// host or adds a path prefix. The default is `false`.
+    ///
+    /// When enabled, the scheme and host of the configured redirect URI are
+    /// replaced by the forwarded ones, and the forwarded prefix is prepended
+    /// to its path. The `Host` header is used if `X-Forwarded-Host` is
+    /// missing. The same redirect URI is used for the authorization request
+    /// and the token exchange, and it must still be registered with the
+    /// service provider.
+    ///
+    /// Only enable this behind a proxy which sets or strips these headers,
+    /// as they are otherwise controlled by the client.
+    pub fn with_forwarded_redirect_uri(mut self, enabled: bool) -> OAuthConfig {
+        self.forwarded_redirect_uri = enabled;
+        self
+    }
+
+    /// Sets the scheme, host, and any base path of the application, such as
+    /// `https://example.com/app`. When the configuration is attached with a
+    /// fairing, the redirect URI is built from this base and the path of the
+    /// mounted redirect handler, replacing the configured redirect URI, so
+    /// that it cannot get out of step with the route.
+    pub fn with_redirect_base(mut self, base: String) -> OAuthConfig {
+        self.redirect_base = Some(base);
+        self
+    }
+
+    /// Sets the name of the state cookie. The default is
+    /// `rocket_oauth2_state`.
+    ///
+    /// Applications which use more than one [`OAuth2`](crate::OAuth2)
+    /// instance, such as separate "log in" and "link account" flows, should
+    /// give each a different name so that a flow in progress for one is not
+    /// overwritten by starting the other.
+    pub fn with_state_cookie_name(mut self, name: String) -> OAuthConfig {
+        self.state_cookie_name = name;
+        self
+    }
+
+    /// Sets how long the state cookie is valid for, which bounds the time
+    /// the user has to complete the authorization. The default is 10
+    /// minutes.
+    ///
+    /// The expiry is also recorded in the cookie's value, so a callback
+    /// which arrives later is rejected even if the browser still sends the
+    /// cookie.
+    pub fn with_state_cookie_max_age(mut self, max_age: Duration) -> OAuthConfig {
+        self.state_cookie_max_age = max_age;
+        self
+    }
+
+    /// Sets how long before it expires an access token is treated as already
+    /// expired by [`OAuth2::ensure_valid`](crate::OAuth2::ensure_valid), so
+    /// that it is refreshed before it can expire while in use. The default is
+    /// zero.
+    pub fn with_expiry_skew(mut self, skew: Duration) -> OAuthConfig {
+        self.expiry_skew = skew;
+        self
+    }
+
+    /// Sets how the state cookie is protected. The default is
+    /// [`StateCookieProtection::Private`], which requires Rocket's secret key
+    /// to be the same for every instance of the application.
+    pub fn with_state_cookie_protection(
+        mut self,
+        protection: StateCookieProtection,
+    ) -> OAuthConfig {
+        self.state_cookie_protection = protection;
+        self
+    }
+
+    /// Sets the [`StateGenerator`] used to generate and validate the `state`
+    /// parameter. The default is [`RandomStateGenerator`].
+    pub fn with_state_generator(mut self, generator: impl StateGenerator) -> OAuthConfig {
+        self.state_generator = Arc::new(generator);
+        self
+    }
+
+    /// Sets a function which is called with the new token whenever
+    /// [`OAuth2::refresh`](crate::OAuth2::refresh) or
+    /// [`OAuth2::ensure_valid`](crate::OAuth2::ensure_valid) refreshes an
+    /// access token, so that the application can store it in one place.
+    ///
+    /// The function is not called for exchanges made with an [`Adapter`]
+    /// directly, or if the refresh fails.
+    ///
+    /// With a configuration loaded from `Rocket.toml`, the hook is set with
+    /// [`OAuth2::fairing_with`](crate::OAuth2::fairing_with).
+    pub fn with_on_token_refreshed<F>(mut self, hook: F) -> OAuthConfig
+    where
+        F: Fn(&TokenResponse) + Send + Sync + 'static,
+    {
+        self.on_token_refreshed = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets a function which is called with a [`FlowEvent`] at each milestone
+    /// of an authorization, for example to record it in an audit log. The
+    /// events never contain secrets, authorization codes, or tokens.
+    ///
+    /// With a configuration loaded from `Rocket.toml`, the hook is set with
+    /// [`OAuth2::fairing_with`](crate::OAuth2::fairing_with).
+    pub fn with_on_event<F>(mut self, hook: F) -> OAuthConfig
+    where
+        F: Fn(&FlowEvent) + Send + Sync + 'static,
+    {
+        self.on_event = Some(Arc::new(hook));
+        self
+    }
+
+    /// Sets how the service provider returns the authorization response.
+    pub fn with_response_mode(mut self, mode: ResponseMode) -> OAuthConfig {
+        self.response_mode = mode;
+        self
+    }
+
+    /// Sets the `response_type` parameter of the authorization request. The
+    /// default is `code`, for the Authorization Code Grant.
+    ///
+    /// Other values, such as `code id_token` for the OpenID Connect hybrid
+    /// flow, are sent as given. However, the redirect handler still expects
+    /// an authorization code in the query string, or in the request body
+    /// with [`ResponseMode::FormPost`], and exchanges it as usual; any other
+    /// response parameters are ignored.
+    pub fn with_response_type(mut self, response_type: String) -> OAuthConfig {
+        self.response_type = response_type;
+        self
+    }
+
+    /// Sets the `audience` parameter of the authorization request, which
+    /// identifies the API the access token is for. Service providers such as
+    /// Auth0 require it to issue an access token for a particular API.
+    pub fn with_audience(mut self, audience: String) -> OAuthConfig {
+        self.audience = Some(audience);
+        self
+    }
+
+    /// Checks that this configuration is usable: the `client_id` must not be
+    /// empty, the redirect URI and every endpoint URI must be valid absolute
+    /// URIs, and the private key used for
+    /// [`ClientAuthentication::PrivateKeyJwt`] must be valid.
+    ///
+    /// [`OAuth2`](crate::OAuth2) fairings call this when they are attached,
+    /// and abort launch if the configuration is invalid.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.client_id.is_empty() {
+            return Err(Error::new_from(
+                ErrorKind::Other,
+                String::from("client_id must not be empty"),
+            ));
+        }
+
+        let uris = [
+            Some(self.redirect_uri.as_str().into()),
+            Some(self.auth_uri()),
+            Some(self.token_uri()),
+            self.userinfo_uri(),
+            self.jwks_uri(),
+            self.revocation_uri(),
+            self.introspection_uri(),
+            self.device_authorization_uri(),
+        ];
+        for uri in uris.iter().flatten() {
+            if let Err(e) = url::Url::parse(uri) {
+                return Err(Error::new_from(ErrorKind::InvalidUri(uri.to_string()), e));
+            }
+        }
+
+        if let ClientAuthentication::PrivateKeyJwt { key, alg, .. } = &self.client_authentication {
+            alg.sign(key, b"")?;
+        }
+
+        if let StateCookieProtection::Signed(key) = &self.state_cookie_protection {
+            if key.len() < MIN_STATE_COOKIE_KEY_LEN {
+                return Err(Error::new_from(
+                    ErrorKind::Other,
+                    format!(
+                        "the state cookie signing key must be at least {} bytes",
+                        MIN_STATE_COOKIE_KEY_LEN
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Constructs a OAuthConfig from Rocket configuration
     pub fn from_config(config: &Config, name: &str) -> config::Result<OAuthConfig> {
         let oauth = config.get_table("oauth")?;
@@ -69,15 +729,251 @@ impl OAuthConfig {
         }?;
 
         let client_id = get_config_string(table, "client_id")?;
-        let client_secret = get_config_string(table, "client_secret")?;
-        let redirect_uri = get_config_string(table, "redirect_uri")?;
+        let client_secret = if table.contains_key("client_secret") {
+            get_config_string(table, "client_secret")?
+        } else {
+            String::new()
+        };
+        let redirect_base = if table.contains_key("redirect_base") {
+            Some(get_config_string(table, "redirect_base")?)
+        } else {
+            None
+        };
+        // The redirect URI is built when the fairing is attached if there is
+        // a redirect base.
+        let redirect_uri = if redirect_base.is_some() && !table.contains_key("redirect_uri") {
+            String::new()
+        } else {
+            get_config_string(table, "redirect_uri")?
+        };
 
-        Ok(OAuthConfig::new(
-            provider,
-            client_id,
-            client_secret,
-            redirect_uri,
-        ))
+        let mut config = OAuthConfig::new(provider, client_id, client_secret, redirect_uri);
+        config.redirect_base = redirect_base;
+
+        if let Some(v) = table.get("pkce") {
+            config.pkce = pkce_from_config_value(v)?;
+        }
+
+        if let Some(v) = table.get("extra_params") {
+            let params = v.as_table().ok_or_else(|| {
+                ConfigError::BadType("extra_params".into(), "table", v.type_str(), None)
+            })?;
+            for name in params.keys() {
+                let value = get_config_string(params, name)?;
+                config.extra_params.push((name.clone(), value));
+            }
+        }
+
+        if let Some(v) = table.get("default_scopes") {
+            let type_error = || {
+                ConfigError::BadType(
+                    "default_scopes".into(),
+                    "array of strings",
+                    v.type_str(),
+                    None,
+                )
+            };
+            for scope in v.as_array().ok_or_else(type_error)? {
+                let scope = scope.as_str().ok_or_else(type_error)?;
+                config.default_scopes.push(scope.to_string());
+            }
+        }
+
+        if let Some(v) = table.get("resources") {
+            let type_error =
+                || ConfigError::BadType("resources".into(), "array of strings", v.type_str(), None);
+            for resource in v.as_array().ok_or_else(type_error)? {
+                let resource = resource.as_str().ok_or_else(type_error)?;
+                config.resources.push(resource.to_string());
+            }
+        }
+
+        if let Some(v) = table.get("token_param_names") {
+            let names = v.as_table().ok_or_else(|| {
+                ConfigError::BadType("token_param_names".into(), "table", v.type_str(), None)
+            })?;
+            for name in names.keys() {
+                let renamed = get_config_string(names, name)?;
+                config.token_param_names.push((name.clone(), renamed));
+            }
+        }
+
+        if let Some(v) = table.get("grant_types") {
+            let grant_types = v.as_table().ok_or_else(|| {
+                ConfigError::BadType("grant_types".into(), "table", v.type_str(), None)
+            })?;
+            for grant_type in grant_types.keys() {
+                let custom = get_config_string(grant_types, grant_type)?;
+                config.grant_types.push((grant_type.clone(), custom));
+            }
+        }
+
+        if table.contains_key("scope_delimiter") {
+            config.scope_delimiter = get_config_string(table, "scope_delimiter")?;
+        }
+
+        if let Some(v) = table.get("include_empty_scope") {
+            config.include_empty_scope = v.as_bool().ok_or_else(|| {
+                ConfigError::BadType("include_empty_scope".into(), "boolean", v.type_str(), None)
+            })?;
+        }
+
+        if table.contains_key("userinfo_uri") {
+            config.userinfo_uri = Some(get_config_string(table, "userinfo_uri")?);
+        }
+
+        if table.contains_key("issuer") {
+            config.issuer = Some(get_config_string(table, "issuer")?);
+        }
+
+        if table.contains_key("jwks_uri") {
+            config.jwks_uri = Some(get_config_string(table, "jwks_uri")?);
+        }
+
+        if table.contains_key("revocation_uri") {
+            config.revocation_uri = Some(get_config_string(table, "revocation_uri")?);
+        }
+
+        if table.contains_key("introspection_uri") {
+            config.introspection_uri = Some(get_config_string(table, "introspection_uri")?);
+        }
+
+        if table.contains_key("device_authorization_uri") {
+            config.device_authorization_uri =
+                Some(get_config_string(table, "device_authorization_uri")?);
+        }
+
+        if table.contains_key("state_cookie_same_site") {
+            let same_site = get_config_string(table, "state_cookie_same_site")?;
+            config.state_cookie_same_site = match same_site.to_ascii_lowercase().as_str() {
+                "strict" => SameSite::Strict,
+                "lax" => SameSite::Lax,
+                "none" => SameSite::None,
+                _ => {
+                    return Err(ConfigError::BadType(
+                        "state_cookie_same_site".into(),
+                        "\"Strict\", \"Lax\", or \"None\"",
+                        "",
+                        None,
+                    ))
+                }
+            };
+        }
+
+        if table.contains_key("state_cookie_name") {
+            config.state_cookie_name = get_config_string(table, "state_cookie_name")?;
+        }
+
+        if let Some(v) = table.get("state_cookie_max_age") {
+            let secs = v.as_integer().filter(|secs| *secs > 0).ok_or_else(|| {
+                ConfigError::BadType(
+                    "state_cookie_max_age".into(),
+                    "positive integer",
+                    v.type_str(),
+                    None,
+                )
+            })?;
+            config.state_cookie_max_age = Duration::from_secs(secs as u64);
+        }
+
+        if let Some(v) = table.get("expiry_skew") {
+            let secs = v.as_integer().filter(|secs| *secs >= 0).ok_or_else(|| {
+                ConfigError::BadType(
+                    "expiry_skew".into(),
+                    "non-negative integer",
+                    v.type_str(),
+                    None,
+                )
+            })?;
+            config.expiry_skew = Duration::from_secs(secs as u64);
+        }
+
+        if table.contains_key("state_cookie_protection") {
+            let protection = get_config_string(table, "state_cookie_protection")?;
+            config.state_cookie_protection = match protection.to_ascii_lowercase().as_str() {
+                "private" => StateCookieProtection::Private,
+                "signed" => {
+                    let key = get_config_string(table, "state_cookie_signing_key")?;
+                    let key = base64::decode(&key).map_err(|_| {
+                        ConfigError::BadType(
+                            "state_cookie_signing_key".into(),
+                            "base64-encoded key",
+                            "string",
+                            None,
+                        )
+                    })?;
+                    StateCookieProtection::Signed(key)
+                }
+                "plain" => StateCookieProtection::Plain,
+                _ => {
+                    return Err(ConfigError::BadType(
+                        "state_cookie_protection".into(),
+                        "\"private\", \"signed\", or \"plain\"",
+                        "string",
+                        None,
+                    ))
+                }
+            };
+        }
+
+        if let Some(v) = table.get("state_cookie_secure") {
+            config.state_cookie_secure = v.as_bool().ok_or_else(|| {
+                ConfigError::BadType("state_cookie_secure".into(), "boolean", v.type_str(), None)
+            })?;
+        }
+
+        if let Some(v) = table.get("forwarded_redirect_uri") {
+            config.forwarded_redirect_uri = v.as_bool().ok_or_else(|| {
+                ConfigError::BadType(
+                    "forwarded_redirect_uri".into(),
+                    "boolean",
+                    v.type_str(),
+                    None,
+                )
+            })?;
+        }
+
+        if table.contains_key("response_mode") {
+            let mode = get_config_string(table, "response_mode")?;
+            config.response_mode = match mode.as_str() {
+                "query" => ResponseMode::Query,
+                "form_post" => ResponseMode::FormPost,
+                _ => {
+                    return Err(ConfigError::BadType(
+                        "response_mode".into(),
+                        "\"query\" or \"form_post\"",
+                        "",
+                        None,
+                    ))
+                }
+            };
+        }
+
+        if table.contains_key("response_type") {
+            config.response_type = get_config_string(table, "response_type")?;
+        }
+
+        if table.contains_key("audience") {
+            config.audience = Some(get_config_string(table, "audience")?);
+        }
+
+        if table.contains_key("client_authentication") {
+            let method = get_config_string(table, "client_authentication")?;
+            config.client_authentication = match method.as_str() {
+                "client_secret_post" => ClientAuthentication::RequestBody,
+                "client_secret_basic" => ClientAuthentication::BasicHeader,
+                _ => {
+                    return Err(ConfigError::BadType(
+                        "client_authentication".into(),
+                        "\"client_secret_post\" or \"client_secret_basic\"",
+                        "",
+                        None,
+                    ))
+                }
+            };
+        }
+
+        Ok(config)
     }
 
     /// Gets the [Provider] for this configuration.
@@ -90,7 +986,8 @@ impl OAuthConfig {
         &self.client_id
     }
 
-    /// Gets the client secret for this configuration.
+    /// Gets the client secret for this configuration. This is empty for
+    /// public clients.
     pub fn client_secret(&self) -> &str {
         &self.client_secret
     }
@@ -99,6 +996,234 @@ impl OAuthConfig {
     pub fn redirect_uri(&self) -> &str {
         &self.redirect_uri
     }
+
+    /// Gets the PKCE challenge method for this configuration, if PKCE is
+    /// enabled.
+    pub fn pkce(&self) -> Option<PkceMethod> {
+        self.pkce
+    }
+
+    /// Gets the method used to authenticate the client to the token endpoint.
+    pub fn client_authentication(&self) -> &ClientAuthentication {
+        &self.client_authentication
+    }
+
+    /// Gets the additional query parameters to include in authorization URIs.
+    pub fn extra_params(&self) -> &[(String, String)] {
+        &self.extra_params
+    }
+
+    /// Gets the scopes requested in every authorization request.
+    pub fn default_scopes(&self) -> &[String] {
+        &self.default_scopes
+    }
+
+    /// Gets the resource indicators (RFC 8707) sent in authorization and token
+    /// requests.
+    pub fn resources(&self) -> &[String] {
+        &self.resources
+    }
+
+    /// Gets the name sent in token request bodies for the standard parameter
+    /// `name`, which is `name` itself unless it has been renamed.
+    pub fn token_param_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.token_param_names
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(name, |(_, renamed)| renamed)
+    }
+
+    /// Gets the `grant_type` sent in token requests in place of the standard
+    /// `grant_type`, which is `grant_type` itself unless it has been
+    /// overridden.
+    pub fn grant_type<'a>(&'a self, grant_type: &'a str) -> &'a str {
+        self.grant_types
+            .iter()
+            .find(|(g, _)| g == grant_type)
+            .map_or(grant_type, |(_, custom)| custom)
+    }
+
+    /// Gets the delimiter used to join scopes in the authorization URI.
+    pub fn scope_delimiter(&self) -> &str {
+        &self.scope_delimiter
+    }
+
+    /// Gets whether an empty `scope` parameter is included in the
+    /// authorization URI when no scopes are requested.
+    pub fn include_empty_scope(&self) -> bool {
+        self.include_empty_scope
+    }
+
+    /// Gets the authorization endpoint URI of the [Provider].
+    pub fn auth_uri(&self) -> Cow<'_, str> {
+        self.provider.auth_uri()
+    }
+
+    /// Gets the token endpoint URI of the [Provider].
+    pub fn token_uri(&self) -> Cow<'_, str> {
+        self.provider.token_uri()
+    }
+
+    /// Gets the OpenID Connect UserInfo endpoint URI for this configuration,
+    /// if the configuration or the [Provider] specifies one.
+    pub fn userinfo_uri(&self) -> Option<Cow<'_, str>> {
+        match &self.userinfo_uri {
+            Some(uri) => Some(Cow::Borrowed(uri)),
+            None => self.provider.userinfo_uri(),
+        }
+    }
+
+    /// Gets the OpenID Connect issuer identifier for this configuration, if
+    /// the configuration or the [Provider] specifies one.
+    pub fn issuer(&self) -> Option<Cow<'_, str>> {
+        match &self.issuer {
+            Some(issuer) => Some(Cow::Borrowed(issuer)),
+            None => self.provider.issuer(),
+        }
+    }
+
+    /// Gets the JSON Web Key Set URI for this configuration, if the
+    /// configuration or the [Provider] specifies one.
+    pub fn jwks_uri(&self) -> Option<Cow<'_, str>> {
+        match &self.jwks_uri {
+            Some(uri) => Some(Cow::Borrowed(uri)),
+            None => self.provider.jwks_uri(),
+        }
+    }
+
+    /// Gets the token revocation URI for this configuration, if the
+    /// configuration or the [Provider] specifies one.
+    pub fn revocation_uri(&self) -> Option<Cow<'_, str>> {
+        match &self.revocation_uri {
+            Some(uri) => Some(Cow::Borrowed(uri)),
+            None => self.provider.revocation_uri(),
+        }
+    }
+
+    /// Gets the token introspection URI for this configuration, if the
+    /// configuration or the [Provider] specifies one.
+    pub fn introspection_uri(&self) -> Option<Cow<'_, str>> {
+        match &self.introspection_uri {
+            Some(uri) => Some(Cow::Borrowed(uri)),
+            None => self.provider.introspection_uri(),
+        }
+    }
+
+    /// Gets the `SameSite` attribute of the state cookie.
+    pub fn state_cookie_same_site(&self) -> SameSite {
+        self.state_cookie_same_site
+    }
+
+    /// Gets whether the state cookie has the `Secure` attribute.
+    pub fn state_cookie_secure(&self) -> bool {
+        self.state_cookie_secure
+    }
+
+    /// Gets whether the redirect URI is derived from the forwarded headers of
+    /// each request.
+    pub fn forwarded_redirect_uri(&self) -> bool {
+        self.forwarded_redirect_uri
+    }
+
+    /// Gets the base URL from which the redirect URI is built, if any.
+    pub fn redirect_base(&self) -> Option<&str> {
+        self.redirect_base.as_deref()
+    }
+
+    /// Returns this configuration with the redirect URI built from the
+    /// redirect base, if there is one, and `callback_uri`.
+    pub(crate) fn with_callback_uri(self, callback_uri: &str) -> OAuthConfig {
+        match &self.redirect_base {
+            Some(base) => {
+                let uri = format!("{}{}", base.trim_end_matches('/'), callback_uri);
+                self.with_redirect_uri(uri)
+            }
+            None => self,
+        }
+    }
+
+    /// Gets the name of the state cookie.
+    pub fn state_cookie_name(&self) -> &str {
+        &self.state_cookie_name
+    }
+
+    /// Gets how the service provider returns the authorization response.
+    pub fn response_mode(&self) -> ResponseMode {
+        self.response_mode
+    }
+
+    /// Gets the `response_type` parameter of the authorization request.
+    pub fn response_type(&self) -> &str {
+        &self.response_type
+    }
+
+    /// Gets the `audience` parameter of the authorization request, if any.
+    pub fn audience(&self) -> Option<&str> {
+        self.audience.as_deref()
+    }
+
+    /// Gets the [`StateGenerator`] for this configuration.
+    pub fn state_generator(&self) -> &dyn StateGenerator {
+        &*self.state_generator
+    }
+
+    /// Calls the function set with [`OAuthConfig::with_on_token_refreshed`],
+    /// if any.
+    pub(crate) fn token_refreshed(&self, token: &TokenResponse) {
+        if let Some(hook) = &self.on_token_refreshed {
+            hook(token);
+        }
+    }
+
+    /// Calls the function set with [`OAuthConfig::with_on_event`], if any.
+    /// `event` is only built if there is one.
+    pub(crate) fn emit(&self, event: impl FnOnce(String) -> FlowEvent) {
+        if let Some(hook) = &self.on_event {
+            let auth_uri = self.auth_uri();
+            let provider = url::Url::parse(&auth_uri)
+                .ok()
+                .and_then(|uri| uri.host_str().map(String::from))
+                .unwrap_or_else(|| auth_uri.into_owned());
+            hook(&event(provider));
+        }
+    }
+
+    /// Gets how the state cookie is protected.
+    pub fn state_cookie_protection(&self) -> &StateCookieProtection {
+        &self.state_cookie_protection
+    }
+
+    /// Gets how long the state cookie is valid for.
+    pub fn state_cookie_max_age(&self) -> Duration {
+        self.state_cookie_max_age
+    }
+
+    /// Gets how long before it expires an access token is treated as already
+    /// expired.
+    pub fn expiry_skew(&self) -> Duration {
+        self.expiry_skew
+    }
+
+    /// Gets the device authorization URI for this configuration, if the
+    /// configuration or the [Provider] specifies one.
+    pub fn device_authorization_uri(&self) -> Option<Cow<'_, str>> {
+        match &self.device_authorization_uri {
+            Some(uri) => Some(Cow::Borrowed(uri)),
+            None => self.provider.device_authorization_uri(),
+        }
+    }
+}
+
+fn pkce_from_config_value(conf: &Value) -> Result<Option<PkceMethod>, ConfigError> {
+    let type_error = || ConfigError::BadType("pkce".into(), "boolean or PKCE method", "", None);
+
+    match conf {
+        Value::Boolean(true) => Ok(Some(PkceMethod::S256)),
+        Value::Boolean(false) => Ok(None),
+        Value::String(s) if s.eq_ignore_ascii_case("S256") => Ok(Some(PkceMethod::S256)),
+        Value::String(s) if s.eq_ignore_ascii_case("plain") => Ok(Some(PkceMethod::Plain)),
+        _ => Err(type_error()),
+    }
 }
 
 fn provider_from_config_value(conf: &Value) -> Result<StaticProvider, ConfigError> {
@@ -1,25 +1,31 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt;
+use std::io::Read;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
 
 use ring::rand::{SecureRandom, SystemRandom};
+use ring::{constant_time, digest, hmac};
 use rocket::fairing::{AdHoc, Fairing};
-use rocket::handler;
+use rocket::handler::{self, Handler};
 use rocket::http::uri::Absolute;
-use rocket::http::{Cookie, Cookies, Method, SameSite, Status};
+use rocket::http::{Cookie, Cookies, Method, Status};
 use rocket::outcome::{IntoOutcome, Outcome};
-use rocket::request::{FormItems, FromForm, Request};
-use rocket::response::{Redirect, Responder};
-use rocket::{Data, Route, State};
-use serde_json::Value;
+use rocket::request::{self, FormItems, FromForm, FromRequest, Request};
+use rocket::response::{self, Redirect, Responder, Response};
+use rocket::{Data, Route};
+use serde_json::{json, Value};
 
-use crate::{Error, ErrorKind, OAuthConfig};
+#[cfg(feature = "openid")]
+use crate::id_token::{IdToken, JwksCache};
+use crate::{
+    DeviceAuthorization, DiscoveredProvider, Error, ErrorKind, OAuthConfig, PkceMethod,
+    ResponseMode, StateCookieProtection,
+};
 
-const STATE_COOKIE_NAME: &str = "rocket_oauth2_state";
-
-// Random generation of state for defense against CSRF.
-// See RFC 6749 §10.12 for more details.
-fn generate_state(rng: &dyn SecureRandom) -> Result<String, Error> {
-    let mut buf = [0; 16]; // 128 bits
-    rng.fill(&mut buf).map_err(|_| {
+fn generate_random(rng: &dyn SecureRandom, buf: &mut [u8]) -> Result<String, Error> {
+    rng.fill(buf).map_err(|_| {
         Error::new_from(
             ErrorKind::Other,
             String::from("Failed to generate random data"),
@@ -28,13 +34,424 @@ fn generate_state(rng: &dyn SecureRandom) -> Result<String, Error> {
     Ok(base64::encode_config(&buf, base64::URL_SAFE_NO_PAD))
 }
 
+// Random generation of state for defense against CSRF.
+// See RFC 6749 §10.12 for more details.
+fn generate_state(rng: &dyn SecureRandom) -> Result<String, Error> {
+    let mut buf = [0; 16]; // 128 bits
+    generate_random(rng, &mut buf)
+}
+
+/// A `StateGenerator` produces the `state` parameter sent in each
+/// authorization request, and checks the value returned in the redirect
+/// callback against the one stored in the state cookie.
+///
+/// The default, [`RandomStateGenerator`], is suitable for most applications.
+/// A custom implementation can, for example, produce a signed state which
+/// carries a payload.
+pub trait StateGenerator: Send + Sync + 'static {
+    /// Generate a new state value. It must be unguessable, as it defends
+    /// against CSRF as described in RFC 6749 §10.12, and at most 512 bytes
+    /// long, as longer states are rejected by the redirect handler.
+    fn generate(&self) -> Result<String, Error>;
+
+    /// Returns whether the `received` state matches the `stored` state.
+    ///
+    /// The default implementation compares them in constant time.
+    ///
+    /// ```rust
+    /// use rocket_oauth2::{RandomStateGenerator, StateGenerator};
+    ///
+    /// let generator = RandomStateGenerator;
+    /// let state = generator.generate().unwrap();
+    /// assert!(generator.validate(&state, &state));
+    /// assert!(!generator.validate("forged", &state));
+    /// assert!(!generator.validate(&state[1..], &state));
+    /// ```
+    fn validate(&self, received: &str, stored: &str) -> bool {
+        constant_time::verify_slices_are_equal(received.as_bytes(), stored.as_bytes()).is_ok()
+    }
+}
+
+/// The default [`StateGenerator`], which generates 128 bits of random data
+/// for each state.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomStateGenerator;
+
+impl StateGenerator for RandomStateGenerator {
+    fn generate(&self) -> Result<String, Error> {
+        generate_state(&SystemRandom::new())
+    }
+}
+
+// Random generation of an OpenID Connect nonce, to associate the ID Token
+// with the authorization request. See OpenID Connect Core 1.0 §15.5.2.
+fn generate_nonce(rng: &dyn SecureRandom) -> Result<String, Error> {
+    let mut buf = [0; 16]; // 128 bits
+    generate_random(rng, &mut buf)
+}
+
+// Random generation of a PKCE code verifier. 32 bytes of random data encode
+// to 43 characters, the minimum length allowed by RFC 7636 §4.1.
+fn generate_code_verifier(rng: &dyn SecureRandom) -> Result<String, Error> {
+    let mut buf = [0; 32]; // 256 bits
+    generate_random(rng, &mut buf)
+}
+
+// Seconds since the Unix epoch, as stored in the state cookie.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Derivation of the code challenge from a code verifier, as in RFC 7636 §4.2.
+fn code_challenge(verifier: &str, method: PkceMethod) -> String {
+    match method {
+        PkceMethod::S256 => {
+            let digest = ring::digest::digest(&ring::digest::SHA256, verifier.as_bytes());
+            base64::encode_config(digest.as_ref(), base64::URL_SAFE_NO_PAD)
+        }
+        PkceMethod::Plain => verifier.to_string(),
+    }
+}
+
+/// The data stored in the state cookie between the authorization redirect
+/// and the redirect callback.
+struct FlowState {
+    state: String,
+    // The client the flow was started for, in case the configuration is
+    // resolved differently when the callback arrives.
+    client_id: String,
+    // Seconds since the Unix epoch after which the flow may not be completed.
+    expires_at: u64,
+    code_verifier: Option<String>,
+    nonce: Option<String>,
+    redirect_uri: Option<String>,
+    return_to: Option<String>,
+}
+
+impl FlowState {
+    fn to_cookie_value(&self) -> String {
+        json!({
+            "state": self.state,
+            "client_id": self.client_id,
+            "expires_at": self.expires_at,
+            "code_verifier": self.code_verifier,
+            "nonce": self.nonce,
+            "redirect_uri": self.redirect_uri,
+            "return_to": self.return_to,
+        })
+        .to_string()
+    }
+
+    fn from_cookie_value(value: &str) -> Option<FlowState> {
+        let data: Value = serde_json::from_str(value).ok()?;
+        Some(FlowState {
+            state: data.get("state")?.as_str()?.to_string(),
+            client_id: data.get("client_id")?.as_str()?.to_string(),
+            expires_at: data.get("expires_at")?.as_u64()?,
+            code_verifier: data
+                .get("code_verifier")
+                .and_then(Value::as_str)
+                .map(String::from),
+            nonce: data.get("nonce").and_then(Value::as_str).map(String::from),
+            redirect_uri: data
+                .get("redirect_uri")
+                .and_then(Value::as_str)
+                .map(String::from),
+            return_to: data
+                .get("return_to")
+                .and_then(Value::as_str)
+                .map(String::from),
+        })
+    }
+}
+
+/// A milestone of an authorization, passed to the function set with
+/// [`OAuthConfig::with_on_event`].
+///
+/// `provider` is the host of the provider's authorization URI, such as
+/// `github.com`. Events never contain secrets, authorization codes, or
+/// tokens.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FlowEvent {
+    /// The user was sent to the provider's authorization page.
+    RedirectIssued {
+        /// The provider the user was sent to.
+        provider: String,
+        /// The requested scopes.
+        scopes: Vec<String>,
+    },
+    /// The provider redirected the user back to the redirect handler with a
+    /// valid state.
+    CallbackReceived {
+        /// The provider the user returned from.
+        provider: String,
+        /// Whether the provider returned an error response.
+        had_error: bool,
+    },
+    /// An authorization code was exchanged for an access token.
+    TokenExchanged {
+        /// The provider which issued the token.
+        provider: String,
+    },
+}
+
+/// Details of the authorization request that led to the current redirect
+/// callback, such as the OpenID Connect `nonce` or the application's
+/// `return_to` URL.
+///
+/// `OAuthState` is a request guard which is only available while handling
+/// the redirect callback, i.e. from a [`Callback`]. Anywhere else, the
+/// request is forwarded.
+///
+/// ```rust
+/// # extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::http::Status;
+/// use rocket::Request;
+/// use rocket_oauth2::{OAuthState, TokenResponse};
+///
+/// fn callback(request: &Request, token: TokenResponse) -> Status {
+///     let state = match request.guard::<OAuthState>().succeeded() {
+///         Some(state) => state,
+///         None => return Status::BadRequest,
+///     };
+///     // Decode the ID Token and compare its `nonce` claim to `state.nonce()`
+///     # let _ = (state, token);
+///     Status::Ok
+/// }
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct OAuthState {
+    nonce: Option<String>,
+    return_to: Option<String>,
+}
+
+impl OAuthState {
+    /// Get the `nonce` sent in the authorization request, if any. This must
+    /// match the `nonce` claim of the ID Token.
+    pub fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    /// Get the value passed to [`OAuth2::get_redirect_with_return_to`], if
+    /// any.
+    ///
+    /// Unless the state cookie is [`StateCookieProtection::Plain`], it cannot
+    /// be modified, so this is the value given by the application. Even so,
+    /// check that it is a URL on the application's own site before
+    /// redirecting to it.
+    pub fn return_to(&self) -> Option<&str> {
+        self.return_to.as_deref()
+    }
+}
+
+// The `OAuthState` of the current request, cached by the redirect handler.
+struct CachedOAuthState(Option<OAuthState>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for OAuthState {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<OAuthState, ()> {
+        match &request.local_cache(|| CachedOAuthState(None)).0 {
+            Some(state) => Outcome::Success(state.clone()),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// An error response from the service provider to the redirect URI, such as
+/// `access_denied` when the user declines the authorization request
+/// (RFC 6749, §4.1.2.1).
+///
+/// When the redirect URI receives an error response with a valid `state`, the
+/// redirect handler forwards the request instead of calling the
+/// [`Callback`]. `OAuthError` is a request guard which is available to the
+/// routes the request is forwarded to. These must be mounted at the redirect
+/// URI with a higher rank:
+///
+/// ```rust
+/// # #![feature(proc_macro_hygiene, decl_macro)]
+/// # #[macro_use] extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket_oauth2::OAuthError;
+///
+/// #[get("/auth/github", rank = 2)]
+/// fn github_error(error: OAuthError) -> String {
+///     if error.error() == "access_denied" {
+///         "You declined to sign in with GitHub.".to_string()
+///     } else {
+///         format!("Sign in failed: {}", error.error())
+///     }
+/// }
+/// # fn main() {}
+/// ```
+///
+/// Error responses delivered with `response_mode=form_post` are only
+/// forwarded if their body is small enough to be buffered by Rocket; larger
+/// ones are rejected with `400 Bad Request`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct OAuthError {
+    error: String,
+    error_description: Option<String>,
+    error_uri: Option<String>,
+    state: String,
+}
+
+impl OAuthError {
+    /// Get the error code, such as `access_denied` or `invalid_scope`.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+    /// Get the human-readable description of the error, if the service
+    /// provider gave one.
+    pub fn error_description(&self) -> Option<&str> {
+        self.error_description.as_deref()
+    }
+
+    /// Get the URI of a page describing the error, if the service provider
+    /// gave one.
+    pub fn error_uri(&self) -> Option<&str> {
+        self.error_uri.as_deref()
+    }
+
+    /// Get the `state` of the authorization request. This has already been
+    /// checked against the state cookie.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+}
+
+// The `OAuthError` of the current request, cached by the redirect handler.
+struct CachedOAuthError(Option<OAuthError>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for OAuthError {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<OAuthError, ()> {
+        match &request.local_cache(|| CachedOAuthError(None)).0 {
+            Some(error) => Outcome::Success(error.clone()),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+/// The reason the redirect handler rejected a callback because of its state:
+/// [`ErrorKind::StateCookieMissing`], [`ErrorKind::StateExpired`], or
+/// [`ErrorKind::StateMismatch`].
+///
+/// The handler responds with `400 Bad Request`, and `CallbackError` is a
+/// request guard which is available to the catcher for that status, so that
+/// an expired login can be told apart from a possible attack:
+///
+/// ```rust
+/// # #![feature(proc_macro_hygiene, decl_macro)]
+/// # #[macro_use] extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::Request;
+/// use rocket_oauth2::{CallbackError, ErrorKind};
+///
+/// #[catch(400)]
+/// fn bad_request(request: &Request) -> &'static str {
+///     match request.guard::<CallbackError>().succeeded() {
+///         Some(ref e) if *e.kind() == ErrorKind::StateExpired => {
+///             "Your login took too long, please try again."
+///         }
+///         Some(ref e) if *e.kind() == ErrorKind::StateCookieMissing => {
+///             "Your session expired, please try logging in again."
+///         }
+///         Some(_) => "The login could not be verified.",
+///         None => "Bad request.",
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct CallbackError {
+    kind: ErrorKind,
+}
+
+impl CallbackError {
+    /// Get the kind of error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+}
+
+// The `CallbackError` of the current request, cached by the redirect handler.
+struct CachedCallbackError(Option<CallbackError>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for CallbackError {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<CallbackError, ()> {
+        match &request.local_cache(|| CachedCallbackError(None)).0 {
+            Some(error) => Outcome::Success(error.clone()),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
 /// The token types which can be exchanged with the token endpoint
 #[derive(Clone, PartialEq, Debug)]
 pub enum TokenRequest {
     /// Used for the Authorization Code exchange
-    AuthorizationCode(String),
+    AuthorizationCode {
+        /// The authorization code provided by the service.
+        code: String,
+        /// The PKCE code verifier, if PKCE was used to request the code.
+        code_verifier: Option<String>,
+    },
     /// Used to refresh an access token
     RefreshToken(String),
+    /// Used to poll for an access token with a device code, as described in
+    /// RFC 8628 §3.4
+    DeviceCode(String),
+    /// Used to request an access token for the client itself, as described
+    /// in RFC 6749 §4.4
+    ClientCredentials {
+        /// The scopes to request. The server's default scopes are used if
+        /// this is empty.
+        scopes: Vec<String>,
+    },
+    /// Used to exchange the resource owner's username and password for an
+    /// access token, as described in RFC 6749 §4.3.
+    ///
+    /// This grant is deprecated by the OAuth 2.0 Security Best Current
+    /// Practice, and is only available with the `deprecated_password_grant`
+    /// feature.
+    #[cfg(feature = "deprecated_password_grant")]
+    Password {
+        /// The resource owner's username.
+        username: String,
+        /// The resource owner's password.
+        password: String,
+        /// The scopes to request. The server's default scopes are used if
+        /// this is empty.
+        scopes: Vec<String>,
+    },
+}
+
+/// A hint about the type of a token being revoked, as described in RFC 7009
+/// §2.1.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum TokenTypeHint {
+    /// The token is an access token.
+    AccessToken,
+    /// The token is a refresh token.
+    RefreshToken,
+}
+
+impl TokenTypeHint {
+    /// Returns the value of the `token_type_hint` parameter for this hint.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenTypeHint::AccessToken => "access_token",
+            TokenTypeHint::RefreshToken => "refresh_token",
+        }
+    }
 }
 
 /// The server's response to a successful token exchange, defined in
@@ -42,6 +459,8 @@ pub enum TokenRequest {
 #[derive(Clone, PartialEq, Debug)]
 pub struct TokenResponse {
     data: Value,
+    received_at: SystemTime,
+    delay: Duration,
 }
 
 impl std::convert::TryFrom<Value> for TokenResponse {
@@ -49,8 +468,8 @@ impl std::convert::TryFrom<Value> for TokenResponse {
 
     /// Construct a TokenResponse from a [Value].
     ///
-    /// Returns an [Error] if data is not a JSON Object, or the access_token or token_type is
-    /// missing or not a string.
+    /// Returns an [Error] if data is not a JSON Object, the access_token is
+    /// missing or not a string, or the token_type is not a string.
     fn try_from(data: Value) -> Result<Self, Error> {
         if !data.is_object() {
             return Err(Error::new_from(
@@ -68,20 +487,51 @@ impl std::convert::TryFrom<Value> for TokenResponse {
             }
         }
         match data.get("token_type") {
+            None => (),
             Some(val) if val.is_string() => (),
             _ => {
                 return Err(Error::new_from(
                     ErrorKind::ExchangeFailure,
-                    String::from("TokenResponse token_type was missing or not a string"),
+                    String::from("TokenResponse token_type was not a string"),
                 ))
             }
         }
 
-        Ok(Self { data })
+        Ok(Self {
+            data,
+            received_at: SystemTime::now(),
+            delay: Duration::from_secs(0),
+        })
     }
 }
 
 impl TokenResponse {
+    /// Construct a TokenResponse from a [Value], as with its `TryFrom`
+    /// implementation. This is useful for custom adapters, and for testing
+    /// callbacks without performing a token exchange.
+    ///
+    /// Returns an [Error] if data is not a JSON Object, the access_token is
+    /// missing or not a string, or the token_type is not a string.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde_json;
+    /// use rocket_oauth2::TokenResponse;
+    /// use serde_json::json;
+    ///
+    /// let token = TokenResponse::from_value(json!({
+    ///     "access_token": "abc",
+    ///     "token_type": "bearer",
+    /// }))
+    /// .unwrap();
+    /// assert_eq!(token.access_token(), "abc");
+    ///
+    /// assert!(TokenResponse::from_value(json!({ "token_type": "bearer" })).is_err());
+    /// ```
+    pub fn from_value(data: Value) -> Result<TokenResponse, Error> {
+        std::convert::TryFrom::try_from(data)
+    }
+
     /// Get the TokenResponse data as a raw JSON [Value]. It is guaranteed to
     /// be of type Object.
     pub fn as_value(&self) -> &Value {
@@ -96,22 +546,153 @@ impl TokenResponse {
             .expect("access_token required at construction")
     }
 
-    /// Get the type of token, described in RFC 6749 §7.1.
+    /// Get the type of token, described in RFC 6749 §7.1, exactly as the
+    /// authorization server returned it. Token types are case-insensitive.
+    ///
+    /// Some servers omit the token type, even though RFC 6749 requires it; for
+    /// these, `"bearer"` is returned.
     pub fn token_type(&self) -> &str {
         self.data
             .get("token_type")
             .and_then(Value::as_str)
-            .expect("token_type required at construction")
+            .unwrap_or("bearer")
+    }
+
+    /// Get the value of the `Authorization` header with which to present the
+    /// access token to a resource server, based on the
+    /// [`token_type`](TokenResponse::token_type).
+    ///
+    /// Bearer tokens (RFC 6750) are always presented with the `Bearer`
+    /// scheme, regardless of the case in which the server returned the token
+    /// type. Other token types are used as the scheme as given.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde_json;
+    /// use std::convert::TryFrom;
+    /// use rocket_oauth2::TokenResponse;
+    ///
+    /// let token = TokenResponse::try_from(serde_json::json!({
+    ///     "access_token": "abc123",
+    ///     "token_type": "bearer",
+    /// })).unwrap();
+    /// assert_eq!(token.authorization_header(), "Bearer abc123");
+    ///
+    /// // Some servers omit the token type, which defaults to bearer.
+    /// let token = TokenResponse::try_from(serde_json::json!({
+    ///     "access_token": "abc123",
+    /// })).unwrap();
+    /// assert_eq!(token.token_type(), "bearer");
+    /// assert_eq!(token.authorization_header(), "Bearer abc123");
+    /// ```
+    pub fn authorization_header(&self) -> String {
+        let token_type = self.token_type();
+        if token_type.eq_ignore_ascii_case("bearer") {
+            format!("Bearer {}", self.access_token())
+        } else {
+            format!("{} {}", token_type, self.access_token())
+        }
     }
 
     /// Get the lifetime in seconds of the access token, if the authorization server provided one.
+    ///
+    /// Some servers send the lifetime as a string, such as `"3600"`, which is
+    /// also accepted.
     pub fn expires_in(&self) -> Option<i64> {
-        self.data.get("expires_in").and_then(Value::as_i64)
+        match self.data.get("expires_in")? {
+            Value::String(expires_in) => expires_in.trim().parse().ok(),
+            expires_in => expires_in.as_i64(),
+        }
+    }
+
+    /// Get the lifetime of the access token as a `Duration`, if the
+    /// authorization server provided a non-negative one.
+    pub fn expires_in_duration(&self) -> Option<Duration> {
+        let expires_in = self.expires_in()?;
+        if expires_in < 0 {
+            return None;
+        }
+        Some(Duration::from_secs(expires_in as u64))
+    }
+
+    /// Get the time at which the access token expires, by the local clock,
+    /// if the authorization server provided a lifetime.
+    ///
+    /// The lifetime is counted from
+    /// [`received_at`](TokenResponse::received_at). If the built-in adapters
+    /// received a `Date` header up to five minutes earlier than that, the
+    /// difference is treated as the time the response took to arrive, and
+    /// the lifetime is counted from the `Date` instead. Other `Date` values,
+    /// which can only be explained by the clocks disagreeing, are ignored.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        let issued_at = self
+            .received_at
+            .checked_sub(self.delay)
+            .unwrap_or(self.received_at);
+        Some(issued_at + self.expires_in_duration()?)
+    }
+
+    /// Get the time at which the response was received, by the local clock.
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// Count the token lifetime from `date`, the `Date` header of the
+    /// response, if it is plausibly the time the response was sent.
+    #[cfg(any(feature = "http_client_adapter", feature = "reqwest_adapter"))]
+    pub(crate) fn with_date(mut self, date: SystemTime) -> TokenResponse {
+        const MAX_DELAY: Duration = Duration::from_secs(5 * 60);
+
+        self.delay = match self.received_at.duration_since(date) {
+            Ok(delay) if delay <= MAX_DELAY => delay,
+            _ => Duration::from_secs(0),
+        };
+        self
+    }
+
+    /// Returns `true` if the access token has expired. Tokens without a known
+    /// lifetime are never considered expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::from_secs(0))
     }
 
-    /// Get the refresh token, if the server provided one.
+    /// Returns `true` if the access token has expired or will expire within
+    /// `duration`. Tokens without a known lifetime are never considered
+    /// expired.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        match self.expires_at() {
+            Some(expires_at) => expires_at <= SystemTime::now() + duration,
+            None => false,
+        }
+    }
+
+    /// Get the refresh token, if the server provided one. An empty
+    /// `refresh_token` is treated as missing.
+    ///
+    /// Some providers only issue a refresh token when asked to, such as
+    /// Google with `access_type=offline`.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde_json;
+    /// use rocket_oauth2::TokenResponse;
+    /// use serde_json::json;
+    ///
+    /// let token = |data| TokenResponse::from_value(data).unwrap();
+    /// let with = token(json!({ "access_token": "a", "refresh_token": "r" }));
+    /// assert_eq!(with.refresh_token(), Some("r"));
+    ///
+    /// let without = token(json!({ "access_token": "a" }));
+    /// assert_eq!(without.refresh_token(), None);
+    ///
+    /// let empty = token(json!({ "access_token": "a", "refresh_token": "" }));
+    /// assert_eq!(empty.refresh_token(), None);
+    /// ```
     pub fn refresh_token(&self) -> Option<&str> {
-        self.data.get("refresh_token").and_then(Value::as_str)
+        self.data
+            .get("refresh_token")
+            .and_then(Value::as_str)
+            .filter(|token| !token.is_empty())
     }
 
     /// Get the (space-separated) list of scopes associated with the access
@@ -124,20 +705,328 @@ impl TokenResponse {
     pub fn scope(&self) -> Option<&str> {
         self.data.get("scope").and_then(Value::as_str)
     }
+
+    /// Get the individual scopes associated with the access token, split from
+    /// [`scope`](TokenResponse::scope). Commas are accepted as separators in
+    /// addition to spaces, since some providers (such as GitHub) use them.
+    ///
+    /// The granted scopes may differ from the requested ones, for example if
+    /// the user declined some permissions.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde_json;
+    /// use std::convert::TryFrom;
+    /// use rocket_oauth2::TokenResponse;
+    ///
+    /// for scope in &["a b c", "a,b,c", "a, b, c"] {
+    ///     let token = TokenResponse::try_from(serde_json::json!({
+    ///         "access_token": "abc123",
+    ///         "token_type": "bearer",
+    ///         "scope": scope,
+    ///     })).unwrap();
+    ///     assert_eq!(token.scopes(), Some(vec!["a", "b", "c"]));
+    /// }
+    /// ```
+    pub fn scopes(&self) -> Option<Vec<&str>> {
+        self.scope().map(|scope| {
+            scope
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+    }
+
+    /// Returns `true` if `scope` is one of the [`scopes`](TokenResponse::scopes)
+    /// granted with the access token, for example to check that the user did
+    /// not decline a required permission.
+    ///
+    /// Returns `false` if the provider did not say which scopes were granted,
+    /// neither in the token response nor in the redirect to the callback.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde_json;
+    /// use rocket_oauth2::TokenResponse;
+    ///
+    /// let token = TokenResponse::from_value(serde_json::json!({
+    ///     "access_token": "abc123",
+    ///     "scope": "read:user user:email",
+    /// })).unwrap();
+    /// assert!(token.has_scope("user:email"));
+    /// assert!(!token.has_scope("repo"));
+    /// ```
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().is_some_and(|scopes| scopes.contains(&scope))
+    }
+
+    /// Deserialize the TokenResponse data into `E`, to read non-standard
+    /// fields returned by some providers.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde;
+    /// use rocket_oauth2::TokenResponse;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct SlackTeam {
+    ///     id: String,
+    ///     name: String,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct SlackFields {
+    ///     team: SlackTeam,
+    /// }
+    ///
+    /// fn team_name(token: &TokenResponse) -> Result<String, rocket_oauth2::Error> {
+    ///     let fields: SlackFields = token.extra()?;
+    ///     Ok(fields.team.name)
+    /// }
+    /// ```
+    pub fn extra<E: serde::de::DeserializeOwned>(&self) -> Result<E, Error> {
+        E::deserialize(&self.data).map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))
+    }
+
+    /// Get the OpenID Connect ID Token, if the server provided one. This is
+    /// the raw JWT described in OpenID Connect Core 1.0 §2; it is not
+    /// validated.
+    pub fn id_token(&self) -> Option<&str> {
+        self.data.get("id_token").and_then(Value::as_str)
+    }
+
+    /// Decode the claims of the OpenID Connect ID Token into `C`.
+    ///
+    /// **This does not verify the signature of the ID Token**, or check any
+    /// of its claims such as `iss`, `aud`, or `exp`. The claims should only
+    /// be trusted if the token was received directly from the token endpoint
+    /// over TLS, as is the case for tokens passed to a [`Callback`].
+    ///
+    /// Returns an [Error] if there is no ID Token, or it is not a JWT whose
+    /// payload can be deserialized into `C`.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde;
+    /// use rocket_oauth2::TokenResponse;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Claims {
+    ///     sub: String,
+    ///     email: Option<String>,
+    ///     name: Option<String>,
+    /// }
+    ///
+    /// fn user_id(token: &TokenResponse) -> Result<String, rocket_oauth2::Error> {
+    ///     let claims: Claims = token.decode_id_token()?;
+    ///     Ok(claims.sub)
+    /// }
+    /// ```
+    #[cfg(feature = "openid")]
+    pub fn decode_id_token<C: serde::de::DeserializeOwned>(&self) -> Result<C, Error> {
+        let id_token = self.id_token().ok_or_else(|| {
+            Error::new_from(
+                ErrorKind::InvalidIdToken,
+                String::from("TokenResponse id_token was missing or not a string"),
+            )
+        })?;
+
+        let payload = match id_token.split('.').collect::<Vec<_>>().as_slice() {
+            [_header, payload, _signature] => *payload,
+            _ => {
+                return Err(Error::new_from(
+                    ErrorKind::InvalidIdToken,
+                    String::from("id_token is not a JWT"),
+                ))
+            }
+        };
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))?;
+
+        serde_json::from_slice(&payload).map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))
+    }
+}
+
+/// The parts of a [`TokenResponse`] that an application stores between
+/// requests, for use with [`OAuth2::ensure_valid`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StoredToken {
+    /// The access token.
+    pub access_token: String,
+    /// The type of the access token, usually `"bearer"`.
+    pub token_type: String,
+    /// The time at which the access token expires, if known.
+    pub expires_at: Option<SystemTime>,
+    /// The refresh token, if the server provided one.
+    pub refresh_token: Option<String>,
+}
+
+impl StoredToken {
+    /// Returns `true` if the access token has expired. Tokens without a known
+    /// expiry time are never considered expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::from_secs(0))
+    }
+
+    /// Returns `true` if the access token has expired or will expire within
+    /// `duration`. Tokens without a known expiry time are never considered
+    /// expired.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= SystemTime::now() + duration,
+            None => false,
+        }
+    }
+}
+
+impl From<&TokenResponse> for StoredToken {
+    fn from(token: &TokenResponse) -> StoredToken {
+        StoredToken {
+            access_token: token.access_token().to_string(),
+            token_type: token.token_type().to_string(),
+            expires_at: token.expires_at(),
+            refresh_token: token.refresh_token().map(String::from),
+        }
+    }
+}
+
+/// The server's response to a token introspection request, defined in
+/// RFC 7662 §2.2.
+///
+/// Only `active` is required to be present. The other members, including any
+/// provider-specific ones, are available from [`Introspection::as_value`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct Introspection {
+    data: Value,
+}
+
+impl std::convert::TryFrom<Value> for Introspection {
+    type Error = Error;
+
+    /// Construct an Introspection from a [Value].
+    ///
+    /// Returns an [Error] if data is not a JSON Object, or active is missing
+    /// or not a boolean.
+    fn try_from(data: Value) -> Result<Self, Error> {
+        if !data.is_object() {
+            return Err(Error::new_from(
+                ErrorKind::ExchangeFailure,
+                String::from("Introspection data was not an object"),
+            ));
+        }
+        match data.get("active") {
+            Some(val) if val.is_boolean() => (),
+            _ => {
+                return Err(Error::new_from(
+                    ErrorKind::ExchangeFailure,
+                    String::from("Introspection active was missing or not a boolean"),
+                ))
+            }
+        }
+
+        Ok(Self { data })
+    }
+}
+
+impl Introspection {
+    /// Get the Introspection data as a raw JSON [Value]. It is guaranteed to
+    /// be of type Object.
+    pub fn as_value(&self) -> &Value {
+        &self.data
+    }
+
+    /// Whether the token is currently active. If this is `false`, no other
+    /// information about the token is provided.
+    pub fn active(&self) -> bool {
+        self.data
+            .get("active")
+            .and_then(Value::as_bool)
+            .expect("active required at construction")
+    }
+
+    /// Get the (space-separated) list of scopes associated with the token,
+    /// if provided.
+    pub fn scope(&self) -> Option<&str> {
+        self.data.get("scope").and_then(Value::as_str)
+    }
+
+    /// Get the expiry time of the token as seconds since the Unix epoch, if
+    /// provided.
+    pub fn exp(&self) -> Option<i64> {
+        self.data.get("exp").and_then(Value::as_i64)
+    }
+
+    /// Get the subject of the token, usually a user identifier, if provided.
+    pub fn sub(&self) -> Option<&str> {
+        self.data.get("sub").and_then(Value::as_str)
+    }
 }
 
 /// An OAuth2 `Adapater` can be implemented by any type that facilitates the
 /// Authorization Code Grant as described in RFC 6749 §4.1. The implementing
 /// type must be able to generate an authorization URI and perform the token
 /// exchange.
+///
+/// `Adapter`s are shared between Rocket's worker threads, so they must be
+/// `Send + Sync + 'static`. The trait is synchronous: its methods are called
+/// from request handlers and may block until the server responds. Only
+/// [`authorization_uri`](Adapter::authorization_uri) and
+/// [`exchange_code`](Adapter::exchange_code) must be implemented; the other
+/// methods return an error by default.
+///
+/// A minimal implementation looks like this:
+///
+/// ```rust
+/// # extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::http::ext::IntoOwned;
+/// use rocket::http::uri::Absolute;
+/// use rocket_oauth2::{Adapter, Error, ErrorKind, OAuthConfig, TokenRequest, TokenResponse};
+///
+/// struct NoopAdapter;
+///
+/// impl Adapter for NoopAdapter {
+///     fn authorization_uri(
+///         &self,
+///         config: &OAuthConfig,
+///         state: &str,
+///         scopes: &[&str],
+///         extra_params: &[(&str, &str)],
+///     ) -> Result<Absolute<'static>, Error> {
+///         // Build the URI from config.provider().auth_uri() and the parameters
+///         # let _ = (state, scopes, extra_params);
+///         let uri = config.provider().auth_uri();
+///         Absolute::parse(&uri)
+///             .map(|uri| uri.into_owned())
+///             .map_err(|_| Error::new(ErrorKind::InvalidUri(uri.to_string())))
+///     }
+///
+///     fn exchange_code(
+///         &self,
+///         config: &OAuthConfig,
+///         token: TokenRequest,
+///     ) -> Result<TokenResponse, Error> {
+///         // POST the request to config.provider().token_uri() and parse the
+///         // response with TokenResponse::try_from
+///         # let _ = (config, token);
+///         Err(Error::new(ErrorKind::Unsupported))
+///     }
+/// }
+/// ```
 pub trait Adapter: Send + Sync + 'static {
     /// Generate an authorization URI as described by RFC 6749 §4.1.1
     /// given configuration, state, and scopes.
+    ///
+    /// `extra_params` are additional query parameters which must be appended
+    /// to the URI, such as the PKCE `code_challenge`.
     fn authorization_uri(
         &self,
         config: &OAuthConfig,
         state: &str,
         scopes: &[&str],
+        extra_params: &[(&str, &str)],
     ) -> Result<Absolute<'static>, Error>;
 
     /// Perform the token exchange in accordance with RFC 6749 §4.1.3 given the
@@ -147,18 +1036,94 @@ pub trait Adapter: Send + Sync + 'static {
         config: &OAuthConfig,
         token: TokenRequest,
     ) -> Result<TokenResponse, Error>;
-}
 
-/// An OAuth2 `Callback` implements application-specific OAuth client logic,
-/// such as setting login cookies and making database and API requests. It is
-/// tied to a specific `Adapter`, and will recieve an instance of the Adapter's
-/// `Token` type.
-pub trait Callback: Send + Sync + 'static {
-    // TODO: Relax 'static. Would this need GAT/ATC?
-    /// The callback Responder type.
-    type Responder: Responder<'static>;
+    /// Fetch and parse the OpenID Connect discovery document of the provider
+    /// identified by `issuer`, as described by OpenID Connect Discovery 1.0
+    /// §4.
+    ///
+    /// The default implementation returns an error of kind
+    /// [`ErrorKind::DiscoveryFailure`].
+    fn discover(&self, issuer: &str) -> Result<DiscoveredProvider, Error> {
+        Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            format!("this adapter does not support discovery of '{}'", issuer),
+        ))
+    }
 
-    /// This method will be called when a token exchange has successfully
+    /// Fetch the JSON Web Key Set (RFC 7517 §5) at `jwks_uri`, used to verify
+    /// the signatures of OpenID Connect ID Tokens.
+    ///
+    /// The default implementation returns an error of kind
+    /// [`ErrorKind::DiscoveryFailure`].
+    fn fetch_jwks(&self, jwks_uri: &str) -> Result<Value, Error> {
+        Err(Error::new_from(
+            ErrorKind::DiscoveryFailure,
+            format!("this adapter does not support fetching '{}'", jwks_uri),
+        ))
+    }
+
+    /// Revoke `token` at the revocation endpoint of the configuration, as
+    /// described in RFC 7009 §2.
+    ///
+    /// Implementations should return an error of kind
+    /// [`ErrorKind::Unsupported`] if the configuration has no revocation
+    /// endpoint. The default implementation always does.
+    fn revoke(
+        &self,
+        config: &OAuthConfig,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), Error> {
+        let _ = (config, token, token_type_hint);
+        Err(Error::new_from(
+            ErrorKind::Unsupported,
+            String::from("this adapter does not support token revocation"),
+        ))
+    }
+
+    /// Query the introspection endpoint of the configuration about `token`,
+    /// as described in RFC 7662 §2.
+    ///
+    /// Implementations should return an error of kind
+    /// [`ErrorKind::Unsupported`] if the configuration has no introspection
+    /// endpoint. The default implementation always does.
+    fn introspect(&self, config: &OAuthConfig, token: &str) -> Result<Introspection, Error> {
+        let _ = (config, token);
+        Err(Error::new_from(
+            ErrorKind::Unsupported,
+            String::from("this adapter does not support token introspection"),
+        ))
+    }
+
+    /// Request a device code from the device authorization endpoint of the
+    /// configuration, as described in RFC 8628 §3.1.
+    ///
+    /// Implementations should return an error of kind
+    /// [`ErrorKind::Unsupported`] if the configuration has no device
+    /// authorization endpoint. The default implementation always does.
+    fn device_authorization(
+        &self,
+        config: &OAuthConfig,
+        scopes: &[&str],
+    ) -> Result<DeviceAuthorization, Error> {
+        let _ = (config, scopes);
+        Err(Error::new_from(
+            ErrorKind::Unsupported,
+            String::from("this adapter does not support device authorization"),
+        ))
+    }
+}
+
+/// An OAuth2 `Callback` implements application-specific OAuth client logic,
+/// such as setting login cookies and making database and API requests. It is
+/// tied to a specific `Adapter`, and will recieve an instance of the Adapter's
+/// `Token` type.
+pub trait Callback: Send + Sync + 'static {
+    // TODO: Relax 'static. Would this need GAT/ATC?
+    /// The callback Responder type.
+    type Responder: Responder<'static>;
+
+    /// This method will be called when a token exchange has successfully
     /// completed and will be provided with the request and the token.
     /// Implementors should perform application-specific logic here, such as
     /// checking a database or setting a login cookie.
@@ -177,31 +1142,334 @@ where
     }
 }
 
+/// A [`Callback`] which is given the request's cookies instead of the
+/// request, for the common case of storing the token or a session in a
+/// cookie.
+///
+/// The redirect handler which exchanges the code and calls the callback is
+/// mounted by the fairing, so no route has to be written for it.
+///
+/// ```rust
+/// # extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::http::{Cookie, Cookies};
+/// use rocket::response::Redirect;
+/// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+/// use rocket_oauth2::{CookieCallback, OAuth2, TokenResponse};
+///
+/// fn github_callback(token: TokenResponse, cookies: &mut Cookies<'_>) -> Redirect {
+///     cookies.add_private(Cookie::new("token", token.access_token().to_string()));
+///     Redirect::to("/")
+/// }
+///
+/// # fn check_only() {
+/// rocket::ignite().attach(OAuth2::fairing(
+///     HyperSyncRustlsAdapter::new(),
+///     CookieCallback(github_callback),
+///     "github",
+///     "/auth/github",
+///     Some(("/login/github", vec!["user:read".to_string()])),
+/// ));
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CookieCallback<F>(pub F);
+
+impl<F, R> Callback for CookieCallback<F>
+where
+    F: Fn(TokenResponse, &mut Cookies<'_>) -> R + Send + Sync + 'static,
+    R: Responder<'static>,
+{
+    type Responder = R;
+
+    fn callback(&self, request: &Request<'_>, token: TokenResponse) -> Self::Responder {
+        let mut cookies = request.guard::<Cookies<'_>>().expect("request cookies");
+        (self.0)(token, &mut cookies)
+    }
+}
+
+/// A value of the OpenID Connect `prompt` parameter, which asks the service
+/// provider to show (or not show) particular pages during authorization.
+/// Used with [`OAuth2::get_redirect_with_prompt`].
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum Prompt {
+    /// The provider must not show any pages, and fails the authorization if
+    /// the user is not already logged in and has not already consented.
+    None,
+    /// The user must log in again.
+    Login,
+    /// The user must consent again, even if they have already consented.
+    Consent,
+    /// The user must choose an account, if they are logged in to several.
+    SelectAccount,
+}
+
+impl Prompt {
+    /// Returns the value of the `prompt` parameter for this prompt.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Prompt::None => "none",
+            Prompt::Login => "login",
+            Prompt::Consent => "consent",
+            Prompt::SelectAccount => "select_account",
+        }
+    }
+}
+
+impl fmt::Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// The `OAuth2` structure implements OAuth in a Rocket application by setting
 /// up OAuth-related route handlers.
 ///
 /// ## Redirect handler
 /// `OAuth2` handles the redirect URI. It verifies the `state` token to prevent
 /// CSRF attacks, then instructs the Adapter to perform the token exchange. The
-/// resulting token is passed to the `Callback`.
+/// resulting token is passed to the `Callback`. Error responses, such as when
+/// the user declines the authorization request, are forwarded to the
+/// application's routes with an [`OAuthError`].
 ///
 /// ## Login handler
 /// `OAuth2` optionally handles a login route, which simply redirects to the
 /// authorization URI generated by the `Adapter`. Whether or not `OAuth2` is
 /// handling a login URI, `get_redirect` can be used to get a `Redirect` to the
 /// OAuth login flow manually.
+///
+/// ## Multiple providers
+/// Several fairings with the same `Callback` type can be attached, for
+/// example when two providers share a user information type. The first one is
+/// available as `State<OAuth2<C>>`; every fairing created with
+/// [`OAuth2::fairing`] can be selected by its configuration name with the
+/// [`OAuth2Registry`] in managed state.
+///
+/// `OAuth2` is cheap to clone, and clones share the adapter and callback.
 pub struct OAuth2<C> {
-    adapter: Box<dyn Adapter>,
-    callback: C,
+    adapter: Arc<dyn Adapter>,
+    callback: Arc<C>,
     config: OAuthConfig,
+    resolver: Option<ConfigResolver>,
     login_scopes: Vec<String>,
+    callback_uri: String,
     rng: SystemRandom,
+    #[cfg(feature = "openid")]
+    jwks: Arc<RwLock<JwksCache>>,
+}
+
+type ConfigResolver = Arc<dyn Fn(&Request<'_>) -> OAuthConfig + Send + Sync>;
+
+impl<C> Clone for OAuth2<C> {
+    fn clone(&self) -> Self {
+        OAuth2 {
+            adapter: self.adapter.clone(),
+            callback: self.callback.clone(),
+            config: self.config.clone(),
+            resolver: self.resolver.clone(),
+            login_scopes: self.login_scopes.clone(),
+            callback_uri: self.callback_uri.clone(),
+            rng: SystemRandom::new(),
+            #[cfg(feature = "openid")]
+            jwks: self.jwks.clone(),
+        }
+    }
+}
+
+/// The `OAuth2` instances attached with [`OAuth2::fairing`] for a `Callback`
+/// type, by configuration name. It is placed in managed state by the first
+/// such fairing.
+///
+/// ```rust
+/// # extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::http::{Cookies, Status};
+/// use rocket::response::Redirect;
+/// use rocket::{Request, State};
+/// use rocket_oauth2::{Callback, OAuth2, OAuth2Registry, TokenResponse};
+///
+/// // Shared by the fairings for several providers.
+/// struct UserInfo;
+///
+/// impl Callback for UserInfo {
+///     type Responder = Status;
+///
+///     fn callback(&self, request: &Request<'_>, token: TokenResponse) -> Status {
+///         Status::Ok
+///     }
+/// }
+///
+/// fn login(
+///     provider: String,
+///     oauth2: State<OAuth2Registry<UserInfo>>,
+///     mut cookies: Cookies<'_>,
+/// ) -> Option<Redirect> {
+///     let oauth2 = oauth2.get(&provider)?;
+///     oauth2.get_redirect(&mut cookies, &["user:read"]).ok()
+/// }
+/// ```
+pub struct OAuth2Registry<C> {
+    instances: RwLock<HashMap<String, OAuth2<C>>>,
+}
+
+impl<C> OAuth2Registry<C> {
+    /// Returns the `OAuth2` attached with the configuration name `name`, if
+    /// any.
+    pub fn get(&self, name: &str) -> Option<OAuth2<C>> {
+        self.instances
+            .read()
+            .expect("OAuth2Registry lock poisoned")
+            .get(name)
+            .cloned()
+    }
+
+    /// Returns the configuration names of the attached `OAuth2` instances.
+    pub fn names(&self) -> Vec<String> {
+        self.instances
+            .read()
+            .expect("OAuth2Registry lock poisoned")
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn insert(&self, name: String, oauth2: OAuth2<C>) {
+        self.instances
+            .write()
+            .expect("OAuth2Registry lock poisoned")
+            .insert(name, oauth2);
+    }
+}
+
+impl<C> Default for OAuth2Registry<C> {
+    fn default() -> Self {
+        OAuth2Registry {
+            instances: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl<C: Callback> OAuth2Registry<C> {
+    /// Prepare an authentication redirect with the `OAuth2` attached with
+    /// the configuration name `name`, as with [`OAuth2::get_redirect`].
+    ///
+    /// Returns an error of kind [`ErrorKind::Other`] if there is no such
+    /// `OAuth2`.
+    pub fn get_redirect(
+        &self,
+        name: &str,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+    ) -> Result<Redirect, Error> {
+        match self.get(name) {
+            Some(oauth2) => oauth2.get_redirect(cookies, scopes),
+            None => Err(Error::new_from(
+                ErrorKind::Other,
+                format!("no OAuth2 configuration named {:?} is attached", name),
+            )),
+        }
+    }
+}
+
+impl<C> fmt::Debug for OAuth2Registry<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OAuth2Registry")
+            .field("names", &self.names())
+            .finish()
+    }
+}
+
+/// A `Callback` which wraps any other `Callback`, so that fairings for
+/// several providers can share the type `OAuth2<AnyCallback>` without a
+/// distinct callback type for each. Each instance is then selected by its
+/// configuration name from the `OAuth2Registry<AnyCallback>`.
+///
+/// ```rust,no_run
+/// # extern crate rocket;
+/// # extern crate rocket_oauth2;
+/// use rocket::http::{Cookies, Status};
+/// use rocket::response::Redirect;
+/// use rocket::{Request, State};
+/// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+/// use rocket_oauth2::{AnyCallback, OAuth2, OAuth2Registry, TokenResponse};
+///
+/// fn github_callback(request: &Request, token: TokenResponse) -> Status {
+///     Status::Ok
+/// }
+///
+/// fn google_callback(request: &Request, token: TokenResponse) -> Redirect {
+///     Redirect::to("/")
+/// }
+///
+/// fn login(
+///     provider: String,
+///     oauth2: State<OAuth2Registry<AnyCallback>>,
+///     mut cookies: Cookies<'_>,
+/// ) -> Option<Redirect> {
+///     oauth2.get_redirect(&provider, &mut cookies, &["openid"]).ok()
+/// }
+///
+/// fn main() {
+///     rocket::ignite()
+///         .attach(OAuth2::fairing(
+///             HyperSyncRustlsAdapter::new(),
+///             AnyCallback::new(github_callback),
+///             "github",
+///             "/auth/github",
+///             None,
+///         ))
+///         .attach(OAuth2::fairing(
+///             HyperSyncRustlsAdapter::new(),
+///             AnyCallback::new(google_callback),
+///             "google",
+///             "/auth/google",
+///             None,
+///         ))
+///         .launch();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AnyCallback(Arc<AnyCallbackFn>);
+
+type AnyCallbackFn = dyn Fn(&Request<'_>, TokenResponse) -> response::Result<'static> + Send + Sync;
+
+impl AnyCallback {
+    /// Wrap `callback`, responding with its `Responder`.
+    pub fn new<C: Callback>(callback: C) -> AnyCallback {
+        AnyCallback(Arc::new(move |request, token| {
+            callback.callback(request, token).respond_to(request)
+        }))
+    }
+}
+
+impl Callback for AnyCallback {
+    type Responder = Result<Response<'static>, Status>;
+
+    fn callback(&self, request: &Request<'_>, token: TokenResponse) -> Self::Responder {
+        (self.0)(request, token)
+    }
+}
+
+impl fmt::Debug for AnyCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("AnyCallback").field(&(..)).finish()
+    }
 }
 
 impl<C: Callback> OAuth2<C> {
     /// Returns an OAuth2 fairing. The fairing will place an instance of
     /// `OAuth2<C>` in managed state and mount a redirect handler. It will
     /// also mount a login handler if `login` is `Some`.
+    ///
+    /// The instance is also registered under `config_name` in the
+    /// [`OAuth2Registry`]. If another `OAuth2<C>` is already in managed
+    /// state, it is left in place and this instance is only available from
+    /// the registry.
+    ///
+    /// Options which cannot be set in `Rocket.toml`, such as the hooks set
+    /// with [`OAuthConfig::with_on_token_refreshed`] and
+    /// [`OAuthConfig::with_on_event`], can be added to the loaded
+    /// configuration with [`OAuth2::fairing_with`].
     pub fn fairing<A: Adapter>(
         adapter: A,
         callback: C,
@@ -209,6 +1477,54 @@ impl<C: Callback> OAuth2<C> {
         callback_uri: &str,
         login: Option<(&str, Vec<String>)>,
     ) -> impl Fairing {
+        Self::fairing_with(adapter, callback, config_name, callback_uri, login, |c| c)
+    }
+
+    /// Returns an OAuth2 fairing like [`OAuth2::fairing`], which passes the
+    /// configuration loaded from `Rocket.toml` through `configure` before it
+    /// is used. This is how hooks are registered with the fairing:
+    ///
+    /// ```rust,no_run
+    /// # extern crate rocket;
+    /// # extern crate rocket_oauth2;
+    /// use rocket::http::Status;
+    /// use rocket::Request;
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    /// use rocket_oauth2::{OAuth2, TokenResponse};
+    ///
+    /// fn callback(request: &Request, token: TokenResponse) -> Status {
+    ///     Status::Ok
+    /// }
+    ///
+    /// # fn store_token(token: &TokenResponse) {}
+    /// fn main() {
+    ///     rocket::ignite()
+    ///         .attach(OAuth2::fairing_with(
+    ///             HyperSyncRustlsAdapter::new(),
+    ///             callback,
+    ///             "github",
+    ///             "/auth/github",
+    ///             None,
+    ///             |config| {
+    ///                 config
+    ///                     .with_on_token_refreshed(store_token)
+    ///                     .with_on_event(|event| println!("{:?}", event))
+    ///             },
+    ///         ))
+    ///         .launch();
+    /// }
+    /// ```
+    pub fn fairing_with<A: Adapter, F>(
+        adapter: A,
+        callback: C,
+        config_name: &str,
+        callback_uri: &str,
+        login: Option<(&str, Vec<String>)>,
+        configure: F,
+    ) -> impl Fairing
+    where
+        F: FnOnce(OAuthConfig) -> OAuthConfig + Send + 'static,
+    {
         // Unfortunate allocations, but necessary because on_attach requires 'static
         let config_name = config_name.to_string();
         let callback_uri = callback_uri.to_string();
@@ -216,7 +1532,7 @@ impl<C: Callback> OAuth2<C> {
 
         AdHoc::on_attach("OAuth Init", move |rocket| {
             let config = match OAuthConfig::from_config(rocket.config(), &config_name) {
-                Ok(c) => c,
+                Ok(c) => configure(c),
                 Err(e) => {
                     log::error!("Invalid configuration: {:?}", e);
                     return Err(rocket);
@@ -229,10 +1545,12 @@ impl<C: Callback> OAuth2<C> {
                 new_login = Some((lu.as_str(), new_ls));
             };
 
-            Ok(rocket.attach(Self::custom(
+            Ok(rocket.attach(Self::mount(
                 adapter,
                 callback,
                 config,
+                None,
+                Some(config_name.clone()),
                 &callback_uri,
                 new_login,
             )))
@@ -243,6 +1561,47 @@ impl<C: Callback> OAuth2<C> {
     /// place an instance of `OAuth2<C>` in managed state and mount a
     /// redirect handler. It will also mount a login handler if `login` is
     /// `Some`.
+    ///
+    /// Unlike [`OAuth2::fairing`], the configuration is not read from
+    /// `Rocket.toml`, so it can be built at runtime with [`OAuthConfig::new`],
+    /// for example from a database:
+    ///
+    /// ```rust,no_run
+    /// # extern crate rocket;
+    /// # extern crate rocket_oauth2;
+    /// use rocket::http::Status;
+    /// use rocket::Request;
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    /// use rocket_oauth2::{OAuth2, OAuthConfig, StaticProvider, TokenResponse};
+    ///
+    /// fn callback(request: &Request, token: TokenResponse) -> Status {
+    ///     Status::Ok
+    /// }
+    ///
+    /// # fn load_client_credentials() -> (String, String) { unimplemented!() }
+    /// fn main() {
+    ///     let (client_id, client_secret) = load_client_credentials();
+    ///     let config = OAuthConfig::new(
+    ///         StaticProvider::GitHub,
+    ///         client_id,
+    ///         client_secret,
+    ///         "https://example.com/auth/github".to_string(),
+    ///     );
+    ///
+    ///     rocket::ignite()
+    ///         .attach(OAuth2::custom(
+    ///             HyperSyncRustlsAdapter::new(),
+    ///             callback,
+    ///             config,
+    ///             "/auth/github",
+    ///             Some(("/login/github", vec!["user:read".to_string()])),
+    ///         ))
+    ///         .launch();
+    /// }
+    /// ```
+    ///
+    /// Configurations which are only known while handling a request can be
+    /// used with an [`Adapter`] directly, without a fairing.
     pub fn custom<A: Adapter>(
         adapter: A,
         callback: C,
@@ -250,95 +1609,898 @@ impl<C: Callback> OAuth2<C> {
         callback_uri: &str,
         login: Option<(&str, Vec<String>)>,
     ) -> impl Fairing {
-        let mut routes = Vec::new();
+        Self::mount(adapter, callback, config, None, None, callback_uri, login)
+    }
 
-        routes.push(Route::new(Method::Get, callback_uri, redirect_handler::<C>));
+    /// Returns an OAuth2 fairing which chooses the configuration for each
+    /// request with `resolver`, for example based on the tenant subdomain of
+    /// a multi-tenant application. Otherwise it behaves like
+    /// [`OAuth2::custom`].
+    ///
+    /// The resolved configuration is used by the login handler,
+    /// [`OAuth2::get_redirect_for_request`], and the redirect handler. The
+    /// `client_id` used to start each authorization is stored in the state
+    /// cookie, and the callback is rejected if the configuration resolved for
+    /// it has a different `client_id`. `config` is used by methods which do
+    /// not have access to a request, such as [`OAuth2::refresh`].
+    pub fn custom_with_resolver<A: Adapter, R>(
+        adapter: A,
+        callback: C,
+        config: OAuthConfig,
+        resolver: R,
+        callback_uri: &str,
+        login: Option<(&str, Vec<String>)>,
+    ) -> impl Fairing
+    where
+        R: Fn(&Request<'_>) -> OAuthConfig + Send + Sync + 'static,
+    {
+        let resolver: ConfigResolver = Arc::new(resolver);
+        Self::mount(
+            adapter,
+            callback,
+            config,
+            Some(resolver),
+            None,
+            callback_uri,
+            login,
+        )
+    }
 
+    fn mount<A: Adapter>(
+        adapter: A,
+        callback: C,
+        config: OAuthConfig,
+        resolver: Option<ConfigResolver>,
+        name: Option<String>,
+        callback_uri: &str,
+        login: Option<(&str, Vec<String>)>,
+    ) -> impl Fairing {
         let mut login_scopes = vec![];
+        let mut login_uri = None;
         if let Some((uri, scopes)) = login {
-            routes.push(Route::new(Method::Get, uri, login_handler::<C>));
+            login_uri = Some(uri);
             login_scopes = scopes;
         }
 
         let oauth2 = Self {
-            adapter: Box::new(adapter),
-            callback,
-            config,
+            adapter: Arc::new(adapter),
+            callback: Arc::new(callback),
+            config: config.with_callback_uri(callback_uri),
+            resolver,
             login_scopes,
+            callback_uri: callback_uri.to_string(),
             rng: SystemRandom::new(),
+            #[cfg(feature = "openid")]
+            jwks: Default::default(),
         };
 
-        AdHoc::on_attach("OAuth Mount", |rocket| {
-            Ok(rocket.manage(oauth2).mount("/", routes))
+        let mut routes = Vec::new();
+        let redirect = RedirectHandler(oauth2.clone());
+        routes.push(Route::new(Method::Get, callback_uri, redirect.clone()));
+        routes.push(Route::new(Method::Post, callback_uri, redirect));
+        if let Some(uri) = login_uri {
+            routes.push(Route::new(Method::Get, uri, LoginHandler(oauth2.clone())));
+        }
+
+        AdHoc::on_attach("OAuth Mount", move |mut rocket| {
+            if let Err(e) = oauth2.config.validate() {
+                log::error!("Invalid configuration: {}", e);
+                return Err(rocket);
+            }
+            if rocket.state::<OAuth2Registry<C>>().is_none() {
+                rocket = rocket.manage(OAuth2Registry::<C>::default());
+            }
+            if let Some(name) = name {
+                if let Some(registry) = rocket.state::<OAuth2Registry<C>>() {
+                    registry.insert(name, oauth2.clone());
+                }
+            }
+            if rocket.state::<Self>().is_none() {
+                rocket = rocket.manage(oauth2);
+            }
+            Ok(rocket.mount("/", routes))
         })
     }
 
     /// Prepare an authentication redirect. This sets a state cookie and returns
     /// a `Redirect` to the provider's authorization page.
+    ///
+    /// If PKCE is enabled in the configuration, a code verifier is also
+    /// generated and stored in the state cookie, and the corresponding code
+    /// challenge is included in the authorization URI.
+    ///
+    /// If `scopes` includes `openid`, a `nonce` is also generated, included in
+    /// the authorization URI, and stored in the state cookie. It can be
+    /// retrieved in the callback with the [`OAuthState`] request guard.
     pub fn get_redirect(
         &self,
         cookies: &mut Cookies<'_>,
         scopes: &[&str],
     ) -> Result<Redirect, Error> {
-        let state = generate_state(&self.rng)?;
+        self.get_redirect_extras(cookies, scopes, &[])
+    }
+
+    /// Prepare an authentication redirect like [`OAuth2::get_redirect`], with
+    /// scopes which are computed at runtime, such as from a database.
+    pub fn get_redirect_owned(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: Vec<String>,
+    ) -> Result<Redirect, Error> {
+        let scopes: Vec<_> = scopes.iter().map(String::as_str).collect();
+        self.get_redirect(cookies, &scopes)
+    }
+
+    /// Prepare an authentication redirect with additional query parameters
+    /// in the authorization URI, such as `prompt` or `login_hint`. Parameters
+    /// configured with [`OAuthConfig::with_extra_param`] are always included.
+    pub fn get_redirect_extras(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        extras: &[(&str, &str)],
+    ) -> Result<Redirect, Error> {
+        self.authorize(
+            Cow::Borrowed(&self.config),
+            cookies,
+            scopes,
+            extras,
+            None,
+            None,
+        )
+        .map(|(uri, _)| Redirect::to(uri))
+    }
+
+    /// Prepare an authentication redirect with a `login_hint`, such as the
+    /// user's email address, to pre-fill the provider's login form, and a
+    /// `domain_hint` to skip the identity provider selection of providers
+    /// such as Azure AD. Each hint is only included if it is given.
+    pub fn get_redirect_with_hints(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        login_hint: Option<&str>,
+        domain_hint: Option<&str>,
+    ) -> Result<Redirect, Error> {
+        let mut extras = vec![];
+        if let Some(login_hint) = login_hint {
+            extras.push(("login_hint", login_hint));
+        }
+        if let Some(domain_hint) = domain_hint {
+            extras.push(("domain_hint", domain_hint));
+        }
+        self.get_redirect_extras(cookies, scopes, &extras)
+    }
+
+    /// Prepare an authentication redirect like [`OAuth2::get_redirect`], and
+    /// also return the generated `state`, for example to log it or associate
+    /// it with a server-side record.
+    pub fn get_redirect_with_state(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+    ) -> Result<(Redirect, String), Error> {
+        self.authorize(
+            Cow::Borrowed(&self.config),
+            cookies,
+            scopes,
+            &[],
+            None,
+            None,
+        )
+        .map(|(uri, state)| (Redirect::to(uri), state))
+    }
+
+    /// Prepare an authentication redirect with a `prompt` parameter, such as
+    /// [`Prompt::SelectAccount`] to let the user switch accounts. Several
+    /// prompts are sent separated by spaces; no parameter is sent if `prompt`
+    /// is empty.
+    pub fn get_redirect_with_prompt(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        prompt: &[Prompt],
+    ) -> Result<Redirect, Error> {
+        let prompt: Vec<_> = prompt.iter().map(|p| p.as_str()).collect();
+        let prompt = prompt.join(" ");
+        let mut extras = vec![];
+        if !prompt.is_empty() {
+            extras.push(("prompt", prompt.as_str()));
+        }
+        self.get_redirect_extras(cookies, scopes, &extras)
+    }
+
+    /// Prepare an authentication redirect which requests additional `scopes`
+    /// from a user who has already authorized the application, using
+    /// incremental authorization.
+    ///
+    /// The `include_granted_scopes=true` parameter asks the provider to
+    /// combine the new scopes with those granted before, so that the user
+    /// only has to consent to the new ones and the resulting token covers
+    /// all of them. This parameter is supported by Google; other providers
+    /// ignore it, and the token only covers `scopes`.
+    pub fn get_redirect_incremental(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+    ) -> Result<Redirect, Error> {
+        self.get_redirect_extras(cookies, scopes, &[("include_granted_scopes", "true")])
+    }
+
+    /// Prepare an authentication redirect which remembers `return_to`, such
+    /// as the page the user was on before logging in.
+    ///
+    /// `return_to` is stored in the state cookie, and can be read in the
+    /// callback with the [`OAuthState`] request guard once the state has been
+    /// validated.
+    pub fn get_redirect_with_return_to(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        return_to: &str,
+    ) -> Result<Redirect, Error> {
+        self.authorize(
+            Cow::Borrowed(&self.config),
+            cookies,
+            scopes,
+            &[],
+            None,
+            Some(return_to),
+        )
+        .map(|(uri, _)| Redirect::to(uri))
+    }
+
+    /// Prepare an authentication redirect which uses `redirect_uri` instead
+    /// of the configured redirect URI, for example to match the host of the
+    /// current request when the application is served from several domains.
+    ///
+    /// The redirect URI is stored in the state cookie, and the same URI is
+    /// used in the token exchange when the user is redirected back. It must
+    /// still be registered with the service provider and routed to the
+    /// redirect handler.
+    pub fn get_redirect_with_redirect_uri(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        redirect_uri: &str,
+    ) -> Result<Redirect, Error> {
+        self.authorize(
+            Cow::Borrowed(&self.config),
+            cookies,
+            scopes,
+            &[],
+            Some(redirect_uri),
+            None,
+        )
+        .map(|(uri, _)| Redirect::to(uri))
+    }
+
+    /// Prepare an authentication redirect using the configuration for
+    /// `request`, as chosen by the resolver given to
+    /// [`OAuth2::custom_with_resolver`]. Without a resolver, this is the same
+    /// as [`OAuth2::get_redirect`].
+    pub fn get_redirect_for_request(
+        &self,
+        request: &Request<'_>,
+        scopes: &[&str],
+    ) -> Result<Redirect, Error> {
+        let mut cookies = request.guard::<Cookies<'_>>().expect("request cookies");
+        let config = self.config_for_request(request);
+        self.authorize(config, &mut cookies, scopes, &[], None, None)
+            .map(|(uri, _)| Redirect::to(uri))
+    }
+
+    /// Returns the configuration for `request`, as chosen by the resolver
+    /// given to [`OAuth2::custom_with_resolver`], or the fixed configuration
+    /// if there is no resolver.
+    ///
+    /// If [`OAuthConfig::forwarded_redirect_uri`] is enabled, the redirect
+    /// URI is derived from the request's forwarded headers.
+    pub fn config_for_request(&self, request: &Request<'_>) -> Cow<'_, OAuthConfig> {
+        let config = match &self.resolver {
+            Some(resolver) => Cow::Owned(resolver(request).with_callback_uri(&self.callback_uri)),
+            None => Cow::Borrowed(&self.config),
+        };
+        if !config.forwarded_redirect_uri() {
+            return config;
+        }
+        match forwarded_redirect_uri(request, config.redirect_uri()) {
+            Some(uri) => Cow::Owned(config.into_owned().with_redirect_uri(uri)),
+            None => config,
+        }
+    }
+
+    /// Prepare an authorization URI, returning it as a string rather than a
+    /// `Redirect`. This sets the state cookie exactly like
+    /// [`OAuth2::get_redirect`], for applications which redirect the user
+    /// themselves, such as single-page applications that receive the URI
+    /// from an API.
+    pub fn get_authorize_url(
+        &self,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+    ) -> Result<String, Error> {
+        self.authorize(
+            Cow::Borrowed(&self.config),
+            cookies,
+            scopes,
+            &[],
+            None,
+            None,
+        )
+        .map(|(uri, _)| uri.to_string())
+    }
+
+    /// Build the authorization URI for the given `state` and `scopes` without
+    /// any side effects, for example to assert on it in tests or to log it.
+    ///
+    /// The URI includes the configured authorization parameters, such as the
+    /// `response_mode`, `audience`, and `extra_params`. The PKCE challenge
+    /// and OpenID Connect `nonce` are generated per request and stored in the
+    /// state cookie, so they are not included.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// use rocket_oauth2::{Callback, OAuth2};
+    ///
+    /// fn assert_login_uri<C: Callback>(oauth2: &OAuth2<C>) {
+    ///     let uri = oauth2.authorization_uri("teststate", &["a", "b"]).unwrap();
+    ///     assert!(uri.to_string().contains("state=teststate"));
+    /// }
+    /// ```
+    pub fn authorization_uri(
+        &self,
+        state: &str,
+        scopes: &[&str],
+    ) -> Result<Absolute<'static>, Error> {
+        let scopes = merge_scopes(&self.config, scopes);
+        let extra_params = config_params(&self.config);
+        self.adapter
+            .authorization_uri(&self.config, state, &scopes, &extra_params)
+    }
+
+    /// Prepare an authentication redirect with a `state` chosen by the
+    /// application, without setting the state cookie, for applications which
+    /// keep the state in their own session store.
+    ///
+    /// # Security
+    ///
+    /// The state cookie is what protects the redirect handler against CSRF
+    /// (RFC 6749 §10.12). Without it, the application is responsible for:
+    ///
+    /// * generating an unguessable `state` for each authorization request and
+    ///   binding it to the user's session;
+    /// * handling the redirect URI with its own route, since the redirect
+    ///   handler mounted by the fairing rejects callbacks without a state
+    ///   cookie; and
+    /// * checking that the `state` of the callback matches the stored one
+    ///   before passing the `code` to [`OAuth2::exchange_code`].
+    ///
+    /// PKCE needs the state cookie to store the code verifier, so an error of
+    /// kind [`ErrorKind::Unsupported`] is returned if it is enabled. No OpenID
+    /// Connect `nonce` is sent.
+    pub fn get_redirect_no_cookie(&self, scopes: &[&str], state: &str) -> Result<Redirect, Error> {
+        if self.config.pkce().is_some() {
+            return Err(Error::new_from(
+                ErrorKind::Unsupported,
+                String::from("PKCE requires the state cookie"),
+            ));
+        }
+
+        let uri = self.authorization_uri(state, scopes)?;
+        self.config.emit(|provider| FlowEvent::RedirectIssued {
+            provider,
+            scopes: merge_scopes(&self.config, scopes)
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        });
+        Ok(Redirect::to(uri))
+    }
+
+    /// Exchange an authorization code for an access token, for applications
+    /// which handle the redirect URI themselves, such as after
+    /// [`OAuth2::get_redirect_no_cookie`]. The application must validate the
+    /// `state` of the callback first.
+    pub fn exchange_code(&self, code: &str) -> Result<TokenResponse, Error> {
+        let token = self.adapter.exchange_code(
+            &self.config,
+            TokenRequest::AuthorizationCode {
+                code: code.to_string(),
+                code_verifier: None,
+            },
+        )?;
+        self.config
+            .emit(|provider| FlowEvent::TokenExchanged { provider });
+        Ok(token)
+    }
+
+    fn authorize(
+        &self,
+        config: Cow<'_, OAuthConfig>,
+        cookies: &mut Cookies<'_>,
+        scopes: &[&str],
+        extras: &[(&str, &str)],
+        redirect_uri: Option<&str>,
+        return_to: Option<&str>,
+    ) -> Result<(Absolute<'static>, String), Error> {
+        let config = with_redirect_uri(config, redirect_uri);
+        let state = config.state_generator().generate()?;
+        let scopes = merge_scopes(&config, scopes);
+        let scopes = &scopes[..];
+
+        let mut extra_params = vec![];
+        let mut code_verifier = None;
+        let challenge;
+        if let Some(method) = config.pkce() {
+            let verifier = generate_code_verifier(&self.rng)?;
+            challenge = code_challenge(&verifier, method);
+            extra_params.push(("code_challenge", challenge.as_str()));
+            extra_params.push(("code_challenge_method", method.as_str()));
+            code_verifier = Some(verifier);
+        }
+        let mut nonce = None;
+        if scopes.contains(&"openid") {
+            nonce = Some(generate_nonce(&self.rng)?);
+        }
+        if let Some(nonce) = &nonce {
+            extra_params.push(("nonce", nonce.as_str()));
+        }
+        extra_params.extend(config_params(&config));
+        extra_params.extend_from_slice(extras);
+
         let uri = self
             .adapter
-            .authorization_uri(&self.config, &state, scopes)?;
-        cookies.add_private(
-            Cookie::build(STATE_COOKIE_NAME, state)
-                .same_site(SameSite::Lax)
-                .finish(),
+            .authorization_uri(&config, &state, scopes, &extra_params)?;
+        config.emit(|provider| FlowEvent::RedirectIssued {
+            provider,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        });
+
+        let max_age = config.state_cookie_max_age();
+        let flow = FlowState {
+            state: state.clone(),
+            client_id: config.client_id().to_string(),
+            expires_at: unix_time(SystemTime::now() + max_age),
+            code_verifier,
+            nonce,
+            redirect_uri: redirect_uri.map(String::from),
+            return_to: return_to.map(String::from),
+        };
+        add_state_cookie(
+            cookies,
+            &config,
+            Cookie::build(
+                config.state_cookie_name().to_string(),
+                flow.to_cookie_value(),
+            )
+            .path("/")
+            .http_only(true)
+            .same_site(config.state_cookie_same_site())
+            .secure(config.state_cookie_secure())
+            .max_age(time::Duration::seconds(max_age.as_secs() as i64))
+            .finish(),
         );
-        Ok(Redirect::to(uri))
+        Ok((uri, state))
+    }
+
+    /// Remove the state cookie set by [`OAuth2::get_redirect`] and related
+    /// methods, for example on logout or when the user cancels a login. The
+    /// state cookie holds all of the flow's data, including the PKCE code
+    /// verifier and the OpenID Connect nonce.
+    ///
+    /// Only the cookie named by the fixed configuration is removed; with a
+    /// resolver which changes the cookie name, remove it with the name from
+    /// [`OAuth2::config_for_request`].
+    pub fn remove_state_cookies(&self, cookies: &mut Cookies<'_>) {
+        remove_state_cookie(cookies, &self.config);
     }
 
     /// Request a new access token given a refresh token. The refresh token
     /// must have been returned by the provider in a previous [`TokenResponse`].
+    ///
+    /// This method does not depend on the current request, and blocks until
+    /// the exchange has completed. Outside of a request, where managed state
+    /// is unavailable, the same exchange can be performed by calling
+    /// [`Adapter::exchange_code`] directly with an [`OAuthConfig`] and
+    /// [`TokenRequest::RefreshToken`]:
+    ///
+    /// ```rust,no_run
+    /// # extern crate rocket;
+    /// # extern crate rocket_oauth2;
+    /// use rocket_oauth2::{Adapter, OAuthConfig, StaticProvider, TokenRequest};
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    ///
+    /// # fn refresh_stored_token(stored_refresh_token: String) -> Result<(), rocket_oauth2::Error> {
+    /// let config = OAuthConfig::new(
+    ///     StaticProvider::GitHub,
+    ///     "client id".to_string(),
+    ///     "client secret".to_string(),
+    ///     "http://localhost:8000/auth/github".to_string(),
+    /// );
+    /// let token = HyperSyncRustlsAdapter::new()
+    ///     .exchange_code(&config, TokenRequest::RefreshToken(stored_refresh_token))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The function set with [`OAuthConfig::with_on_token_refreshed`] is
+    /// called with the new token.
     pub fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, Error> {
+        let token = self.exchange_refresh_token(refresh_token)?;
+        self.config.token_refreshed(&token);
+        Ok(token)
+    }
+
+    fn exchange_refresh_token(&self, refresh_token: &str) -> Result<TokenResponse, Error> {
         self.adapter.exchange_code(
             &self.config,
             TokenRequest::RefreshToken(refresh_token.to_string()),
         )
     }
 
+    /// Returns a valid access token for `stored`, refreshing it only if it
+    /// has expired or will expire within the configured
+    /// [`expiry_skew`](OAuthConfig::with_expiry_skew).
+    ///
+    /// If the access token has not expired, it is returned as a
+    /// `TokenResponse` without contacting the provider. Otherwise the refresh
+    /// token is exchanged for a new access token; if the provider does not
+    /// return a new refresh token, the stored one is included in the
+    /// returned `TokenResponse`. Either way, the application should store
+    /// [`StoredToken::from`] the result, for example in the function set with
+    /// [`OAuthConfig::with_on_token_refreshed`], which is called only if the
+    /// token was refreshed.
+    ///
+    /// Returns an error of kind [`ErrorKind::Other`] if the access token has
+    /// expired and there is no refresh token.
+    pub fn ensure_valid(&self, stored: &StoredToken) -> Result<TokenResponse, Error> {
+        if !stored.expires_within(self.config.expiry_skew()) {
+            let mut data = json!({
+                "access_token": stored.access_token,
+                "token_type": stored.token_type,
+            });
+            if let Some(expires_at) = stored.expires_at {
+                let expires_in = expires_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                data["expires_in"] = json!(expires_in.as_secs());
+            }
+            if let Some(refresh_token) = &stored.refresh_token {
+                data["refresh_token"] = json!(refresh_token);
+            }
+            return TokenResponse::from_value(data);
+        }
+
+        let refresh_token = stored.refresh_token.as_deref().ok_or_else(|| {
+            Error::new_from(
+                ErrorKind::Other,
+                String::from("access token expired and no refresh token is available"),
+            )
+        })?;
+        let mut token = self.exchange_refresh_token(refresh_token)?;
+        if token.refresh_token().is_none() {
+            let data = token
+                .data
+                .as_object_mut()
+                .expect("data is guaranteed to be an Object");
+            data.insert(
+                String::from("refresh_token"),
+                Value::String(refresh_token.to_string()),
+            );
+        }
+        self.config.token_refreshed(&token);
+        Ok(token)
+    }
+
+    /// Revoke an access or refresh token at the provider, as described in
+    /// RFC 7009, for example when the user logs out.
+    ///
+    /// Returns an error of kind [`ErrorKind::Unsupported`] if neither the
+    /// configuration nor the provider specifies a revocation endpoint.
+    pub fn revoke(&self, token: &str, token_type_hint: Option<TokenTypeHint>) -> Result<(), Error> {
+        self.adapter.revoke(&self.config, token, token_type_hint)
+    }
+
+    /// Query the provider about an access or refresh token, as described in
+    /// RFC 7662, for example to validate an opaque access token presented to
+    /// a resource server.
+    ///
+    /// Returns an error of kind [`ErrorKind::Unsupported`] if neither the
+    /// configuration nor the provider specifies an introspection endpoint.
+    pub fn introspect(&self, token: &str) -> Result<Introspection, Error> {
+        self.adapter.introspect(&self.config, token)
+    }
+
+    /// Verify an OpenID Connect ID Token and return its claims, as described
+    /// in OpenID Connect Core 1.0 §3.1.3.7.
+    ///
+    /// The signature is verified with the provider's JSON Web Key Set, which
+    /// is fetched from the configured `jwks_uri` and cached. The key set is
+    /// fetched again if it does not contain the key that signed the token,
+    /// for example after the provider has rotated its keys, but at most once
+    /// a minute; until then, such tokens are rejected. Only the `RS256`
+    /// and `ES256` algorithms are supported.
+    ///
+    /// The `iss` claim must match the configured issuer, the `aud` claim must
+    /// include the client ID, and the token must not have expired. If
+    /// `expected_nonce` is given, usually from [`OAuthState::nonce`], the
+    /// `nonce` claim must match it.
+    ///
+    /// Returns an error of kind [`ErrorKind::InvalidIdToken`] if the token
+    /// cannot be verified, and of kind [`ErrorKind::Unsupported`] if no
+    /// `jwks_uri` is configured. Like [`OAuth2::refresh`], this blocks while
+    /// the key set is fetched.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// # extern crate serde;
+    /// use rocket_oauth2::{Callback, Error, OAuth2, OAuthState, TokenResponse};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Claims {
+    ///     sub: String,
+    /// }
+    ///
+    /// fn user_id<C: Callback>(
+    ///     oauth2: &OAuth2<C>,
+    ///     token: &TokenResponse,
+    ///     state: &OAuthState,
+    /// ) -> Result<String, Error> {
+    ///     let id_token = token.id_token().unwrap_or_default();
+    ///     let claims: Claims = oauth2.verify_id_token(id_token, state.nonce())?;
+    ///     Ok(claims.sub)
+    /// }
+    /// ```
+    #[cfg(feature = "openid")]
+    pub fn verify_id_token<T: serde::de::DeserializeOwned>(
+        &self,
+        id_token: &str,
+        expected_nonce: Option<&str>,
+    ) -> Result<T, Error> {
+        let token = IdToken::parse(id_token)?;
+
+        let cached = self
+            .jwks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys
+            .clone();
+        let jwks = match cached {
+            Some(jwks) if token.has_key(&jwks) => jwks,
+            _ => {
+                let jwks_uri = self.config.jwks_uri().ok_or_else(|| {
+                    Error::new_from(
+                        ErrorKind::Unsupported,
+                        String::from("no jwks_uri is configured"),
+                    )
+                })?;
+                let fetch = self
+                    .jwks
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .start_fetch();
+                if !fetch {
+                    return Err(Error::new_from(
+                        ErrorKind::InvalidIdToken,
+                        String::from("no key in the recently fetched JWKS matches the id_token"),
+                    ));
+                }
+                let jwks = self.adapter.fetch_jwks(&jwks_uri)?;
+                self.jwks.write().unwrap_or_else(|e| e.into_inner()).keys = Some(jwks.clone());
+                jwks
+            }
+        };
+
+        token.verify_signature(&jwks)?;
+        let claims = token.validate_claims(&self.config, expected_nonce)?;
+        serde_json::from_value(claims).map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))
+    }
+
+    /// Request an access token for the client itself using the Client
+    /// Credentials Grant (RFC 6749 §4.4), for service-to-service requests.
+    ///
+    /// Like [`OAuth2::refresh`], this does not depend on the current request
+    /// and blocks until the exchange has completed. Outside of a request the
+    /// same exchange can be performed with [`Adapter::exchange_code`] and
+    /// [`TokenRequest::ClientCredentials`].
+    pub fn client_credentials(&self, scopes: &[&str]) -> Result<TokenResponse, Error> {
+        self.adapter.exchange_code(
+            &self.config,
+            TokenRequest::ClientCredentials {
+                scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            },
+        )
+    }
+
+    /// Exchange the resource owner's `username` and `password` for an access
+    /// token using the Resource Owner Password Credentials Grant (RFC 6749
+    /// §4.3).
+    ///
+    /// This grant exposes the user's credentials to the application and is
+    /// deprecated by the OAuth 2.0 Security Best Current Practice. It is
+    /// only intended for migrating legacy systems, and is only available with
+    /// the `deprecated_password_grant` feature. Like
+    /// [`OAuth2::client_credentials`], it blocks until the exchange has
+    /// completed.
+    #[cfg(feature = "deprecated_password_grant")]
+    pub fn password_grant(
+        &self,
+        username: &str,
+        password: &str,
+        scopes: &[&str],
+    ) -> Result<TokenResponse, Error> {
+        self.adapter.exchange_code(
+            &self.config,
+            TokenRequest::Password {
+                username: username.to_string(),
+                password: password.to_string(),
+                scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            },
+        )
+    }
+
+    /// Start a Device Authorization Grant (RFC 8628) for the given `scopes`.
+    ///
+    /// The returned [`DeviceAuthorization`] contains the code to show to the
+    /// user, and can be passed to [`OAuth2::poll_device_authorization`] to
+    /// wait for them to complete the authorization.
+    ///
+    /// Returns an error of kind [`ErrorKind::Unsupported`] if neither the
+    /// configuration nor the provider specifies a device authorization
+    /// endpoint.
+    pub fn start_device_authorization(
+        &self,
+        scopes: &[&str],
+    ) -> Result<DeviceAuthorization, Error> {
+        self.adapter.device_authorization(&self.config, scopes)
+    }
+
+    /// Wait for the user to complete a Device Authorization Grant, blocking
+    /// until a token is issued or the authorization fails. See
+    /// [`DeviceAuthorization::poll`].
+    pub fn poll_device_authorization(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<TokenResponse, Error> {
+        authorization.poll(&*self.adapter, &self.config)
+    }
+
     // TODO: Decide if BadRequest is the appropriate error code.
-    // TODO: What do providers do if they *reject* the authorization?
     /// Handle the redirect callback, delegating to the adapter and callback to
     /// perform the token exchange and application-specific actions.
-    fn handle<'r>(&self, request: &'r Request<'_>, _data: Data) -> handler::Outcome<'r> {
-        // Parse the query data.
-        let query = request.uri().query().into_outcome(Status::BadRequest)?;
+    fn handle<'r>(&self, request: &'r Request<'_>, data: Data) -> handler::Outcome<'r> {
+        // Read the response from the query, or from the body of a form_post
+        // response. The data is kept, if possible, so that error responses
+        // can be forwarded.
+        let (form, data) = if request.method() == Method::Post {
+            if !matches!(request.content_type(), Some(ct) if ct.is_form()) {
+                return handler::Outcome::failure(Status::BadRequest);
+            }
+            if data.peek_complete() {
+                match String::from_utf8(data.peek().to_vec()) {
+                    Ok(body) => (body, Some(data)),
+                    Err(_) => return handler::Outcome::failure(Status::BadRequest),
+                }
+            } else {
+                let mut body = String::new();
+                if data
+                    .open()
+                    .take(MAX_FORM_SIZE)
+                    .read_to_string(&mut body)
+                    .is_err()
+                {
+                    return handler::Outcome::failure(Status::BadRequest);
+                }
+                (body, None)
+            }
+        } else {
+            let query = request.uri().query().into_outcome(Status::BadRequest)?;
+            (query.to_string(), Some(data))
+        };
 
         #[derive(FromForm)]
         struct CallbackQuery {
-            code: String,
+            code: Option<String>,
             state: String,
             // Nonstandard (but see below)
             scope: Option<String>,
+            error: Option<String>,
+            error_description: Option<String>,
+            error_uri: Option<String>,
         }
 
-        let params = match CallbackQuery::from_form(&mut FormItems::from(query), false) {
+        let params = match CallbackQuery::from_form(&mut FormItems::from(form.as_str()), false) {
             Ok(p) => p,
             Err(_) => return handler::Outcome::failure(Status::BadRequest),
         };
+        if params.state.len() > MAX_STATE_LEN {
+            log::warn!(
+                "Rejecting callback: the state is longer than {} bytes",
+                MAX_STATE_LEN
+            );
+            return handler::Outcome::failure(Status::BadRequest);
+        }
 
-        {
+        let reject = |kind| {
+            request.local_cache(|| CachedCallbackError(Some(CallbackError { kind })));
+            handler::Outcome::failure(Status::BadRequest)
+        };
+
+        let config = self.config_for_request(request);
+        let flow = {
             // Verify that the given state is the same one in the cookie.
             // Begin a new scope so that cookies is not kept around too long.
             let mut cookies = request.guard::<Cookies<'_>>().expect("request cookies");
-            match cookies.get_private(STATE_COOKIE_NAME) {
-                Some(ref cookie) if cookie.value() == params.state => {
-                    cookies.remove(cookie.clone());
+            let value = match get_state_cookie(&mut cookies, &config) {
+                Some(value) => value,
+                None => {
+                    log::warn!("Rejecting callback: there is no state cookie");
+                    return reject(ErrorKind::StateCookieMissing);
+                }
+            };
+            match FlowState::from_cookie_value(&value) {
+                Some(flow)
+                    if config
+                        .state_generator()
+                        .validate(&params.state, &flow.state) =>
+                {
+                    remove_state_cookie(&mut cookies, &config);
+                    flow
+                }
+                _ => {
+                    log::warn!("Rejecting callback: the state does not match the cookie");
+                    return reject(ErrorKind::StateMismatch);
                 }
-                _ => return handler::Outcome::failure(Status::BadRequest),
             }
+        };
+        if unix_time(SystemTime::now()) > flow.expires_at {
+            log::warn!("Rejecting callback: the authorization request has expired");
+            return reject(ErrorKind::StateExpired);
         }
+        if flow.client_id != config.client_id() {
+            log::warn!("Rejecting callback: the authorization request was for another client");
+            return reject(ErrorKind::StateMismatch);
+        }
+        config.emit(|provider| FlowEvent::CallbackReceived {
+            provider,
+            had_error: params.error.is_some(),
+        });
+        request.local_cache(|| {
+            CachedOAuthState(Some(OAuthState {
+                nonce: flow.nonce.clone(),
+                return_to: flow.return_to.clone(),
+            }))
+        });
 
-        // Have the adapter perform the token exchange.
-        let token = match self
-            .adapter
-            .exchange_code(&self.config, TokenRequest::AuthorizationCode(params.code))
-        {
+        // Forward error responses to the application's own routes.
+        if let Some(error) = params.error {
+            log::warn!("Authorization failed: {}", error);
+            let error = OAuthError {
+                error,
+                error_description: params.error_description,
+                error_uri: params.error_uri,
+                state: params.state,
+            };
+            request.local_cache(|| CachedOAuthError(Some(error)));
+            return match data {
+                Some(data) => handler::Outcome::forward(data),
+                None => handler::Outcome::failure(Status::BadRequest),
+            };
+        }
+        let code = params.code.into_outcome(Status::BadRequest)?;
+
+        // Have the adapter perform the token exchange, with the same redirect
+        // URI as the authorization request.
+        let config = with_redirect_uri(config, flow.redirect_uri.as_deref());
+        let token = match self.adapter.exchange_code(
+            &config,
+            TokenRequest::AuthorizationCode {
+                code,
+                code_verifier: flow.code_verifier,
+            },
+        ) {
             Ok(mut token) => {
                 // Some providers (at least Strava) provide 'scope' in the callback
                 // parameters instead of the token response as the RFC prescribes.
@@ -351,6 +2513,7 @@ impl<C: Callback> OAuth2<C> {
                 if let (None, Some(scope)) = (data.get("scope"), params.scope) {
                     data.insert(String::from("scope"), Value::String(scope));
                 }
+                config.emit(|provider| FlowEvent::TokenExchanged { provider });
                 token
             }
             Err(e) => {
@@ -371,32 +2534,188 @@ impl<C: fmt::Debug> fmt::Debug for OAuth2<C> {
             .field("adapter", &(..))
             .field("callback", &self.callback)
             .field("config", &self.config)
+            .field("resolver", &self.resolver.as_ref().map(|_| ..))
             .field("login_scopes", &self.login_scopes)
+            .field("callback_uri", &self.callback_uri)
             .finish()
     }
 }
 
-// These cannot be closures becuase of the lifetime parameter.
-// TODO: cross-reference rust-lang/rust issues.
+/// Add the state `cookie`, protected as configured by `config`.
+fn add_state_cookie(cookies: &mut Cookies<'_>, config: &OAuthConfig, mut cookie: Cookie<'static>) {
+    let payload = base64::encode_config(cookie.value(), base64::URL_SAFE_NO_PAD);
+    match config.state_cookie_protection() {
+        StateCookieProtection::Private => cookies.add_private(cookie),
+        StateCookieProtection::Signed(key) => {
+            let signature = sign_state_cookie(key, cookie.name(), &payload);
+            cookie.set_value(format!("{}.{}", payload, signature));
+            cookies.add(cookie);
+        }
+        StateCookieProtection::Plain => {
+            cookie.set_value(payload);
+            cookies.add(cookie);
+        }
+    }
+}
 
-/// Handles the OAuth redirect route
-fn redirect_handler<'r, C: Callback>(request: &'r Request<'_>, data: Data) -> handler::Outcome<'r> {
-    let oauth = match request.guard::<State<'_, OAuth2<C>>>() {
-        Outcome::Success(oauth) => oauth,
-        Outcome::Failure(_) => return handler::Outcome::failure(Status::InternalServerError),
-        Outcome::Forward(()) => unreachable!(),
+/// Get the value of the state cookie, if it is present and its protection
+/// is intact.
+fn get_state_cookie(cookies: &mut Cookies<'_>, config: &OAuthConfig) -> Option<String> {
+    let name = config.state_cookie_name();
+    let payload = match config.state_cookie_protection() {
+        StateCookieProtection::Private => {
+            return cookies.get_private(name).map(|c| c.value().to_string())
+        }
+        StateCookieProtection::Signed(key) => {
+            let value = cookies.get(name)?.value().to_string();
+            let (payload, signature) = value.split_once('.')?;
+            let expected = sign_state_cookie(key, name, payload);
+            constant_time::verify_slices_are_equal(expected.as_bytes(), signature.as_bytes())
+                .ok()?;
+            payload.to_string()
+        }
+        StateCookieProtection::Plain => cookies.get(name)?.value().to_string(),
     };
-    oauth.handle(request, data)
+    let value = base64::decode_config(&payload, base64::URL_SAFE_NO_PAD).ok()?;
+    String::from_utf8(value).ok()
 }
 
-/// Handles a login route, performing a redirect
-fn login_handler<'r, C: Callback>(request: &'r Request<'_>, _data: Data) -> handler::Outcome<'r> {
-    let oauth = match request.guard::<State<'_, OAuth2<C>>>() {
-        Outcome::Success(oauth) => oauth,
-        Outcome::Failure(_) => return handler::Outcome::failure(Status::InternalServerError),
-        Outcome::Forward(()) => unreachable!(),
+/// Remove the state cookie.
+fn remove_state_cookie(cookies: &mut Cookies<'_>, config: &OAuthConfig) {
+    let cookie = Cookie::build(config.state_cookie_name().to_string(), "")
+        .path("/")
+        .finish();
+    match config.state_cookie_protection() {
+        StateCookieProtection::Private => cookies.remove_private(cookie),
+        _ => cookies.remove(cookie),
+    }
+}
+
+/// HMAC the `payload` of the state cookie `name` with `key`. The name is
+/// included so that the value cannot be moved to another cookie.
+fn sign_state_cookie(key: &[u8], name: &str, payload: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, key);
+    let signature = hmac::sign(&key, format!("{}={}", name, payload).as_bytes());
+    base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+}
+
+/// Returns the default scopes of `config` followed by `scopes`, without
+/// duplicates.
+fn merge_scopes<'a>(config: &'a OAuthConfig, scopes: &[&'a str]) -> Vec<&'a str> {
+    let mut merged: Vec<&str> = vec![];
+    let all = config.default_scopes().iter().map(String::as_str);
+    for scope in all.chain(scopes.iter().copied()) {
+        if !merged.contains(&scope) {
+            merged.push(scope);
+        }
+    }
+    merged
+}
+
+/// Returns the authorization URI parameters set by `config`.
+fn config_params(config: &OAuthConfig) -> Vec<(&str, &str)> {
+    let mut params = vec![];
+    if config.response_mode() != ResponseMode::Query {
+        params.push(("response_mode", config.response_mode().as_str()));
+    }
+    if let Some(audience) = config.audience() {
+        params.push(("audience", audience));
+    }
+    for resource in config.resources() {
+        params.push(("resource", resource.as_str()));
+    }
+    params.extend(
+        config
+            .extra_params()
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+    params
+}
+
+/// Returns `config`, with the redirect URI replaced by `redirect_uri` if one
+/// is given.
+fn with_redirect_uri<'a>(
+    config: Cow<'a, OAuthConfig>,
+    redirect_uri: Option<&str>,
+) -> Cow<'a, OAuthConfig> {
+    match redirect_uri {
+        Some(uri) => Cow::Owned(config.into_owned().with_redirect_uri(uri.to_string())),
+        None => config,
+    }
+}
+
+/// Returns `redirect_uri` with the scheme, host, and path prefix given by the
+/// `X-Forwarded-*` headers of `request`.
+fn forwarded_redirect_uri(request: &Request<'_>, redirect_uri: &str) -> Option<String> {
+    let headers = request.headers();
+    // Proxies may append to a list; the first value is the client-facing one.
+    let first = |name| {
+        headers
+            .get_one(name)
+            .and_then(|value: &str| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
     };
-    let mut cookies = request.guard::<Cookies<'_>>().expect("request cookies");
-    let scopes: Vec<_> = oauth.login_scopes.iter().map(String::as_str).collect();
-    handler::Outcome::from(request, oauth.get_redirect(&mut cookies, &scopes))
+
+    let mut uri = url::Url::parse(redirect_uri).ok()?;
+    if let Some(proto) = first("X-Forwarded-Proto") {
+        if proto != "http" && proto != "https" {
+            return None;
+        }
+        uri.set_scheme(proto).ok()?;
+    }
+    if let Some(host) = first("X-Forwarded-Host").or_else(|| first("Host")) {
+        let authority = url::Url::parse(&format!("{}://{}", uri.scheme(), host)).ok()?;
+        uri.set_host(authority.host_str()).ok()?;
+        uri.set_port(authority.port()).ok()?;
+    }
+    if let Some(prefix) = first("X-Forwarded-Prefix") {
+        let path = format!("{}{}", prefix.trim_end_matches('/'), uri.path());
+        uri.set_path(&path);
+    }
+    Some(uri.into())
+}
+
+/// The maximum size of a `form_post` authorization response body.
+const MAX_FORM_SIZE: u64 = 64 * 1024;
+
+/// The maximum length of the `state` parameter of a callback. Longer states
+/// are rejected before they are compared with the state cookie.
+const MAX_STATE_LEN: usize = 512;
+
+// The handlers hold their own `OAuth2` rather than looking it up in managed
+// state, so that several instances with the same `Callback` type can be
+// mounted.
+
+/// Handles the OAuth redirect route
+struct RedirectHandler<C>(OAuth2<C>);
+
+impl<C> Clone for RedirectHandler<C> {
+    fn clone(&self) -> Self {
+        RedirectHandler(self.0.clone())
+    }
+}
+
+impl<C: Callback> Handler for RedirectHandler<C> {
+    fn handle<'r>(&self, request: &'r Request<'_>, data: Data) -> handler::Outcome<'r> {
+        self.0.handle(request, data)
+    }
+}
+
+/// Handles a login route, performing a redirect
+struct LoginHandler<C>(OAuth2<C>);
+
+impl<C> Clone for LoginHandler<C> {
+    fn clone(&self) -> Self {
+        LoginHandler(self.0.clone())
+    }
+}
+
+impl<C: Callback> Handler for LoginHandler<C> {
+    fn handle<'r>(&self, request: &'r Request<'_>, _data: Data) -> handler::Outcome<'r> {
+        let oauth = &self.0;
+        let scopes: Vec<_> = oauth.login_scopes.iter().map(String::as_str).collect();
+        handler::Outcome::from(request, oauth.get_redirect_for_request(request, &scopes))
+    }
 }
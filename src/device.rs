@@ -0,0 +1,165 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::{Adapter, Error, ErrorCode, ErrorKind, OAuthConfig, TokenRequest, TokenResponse};
+
+/// The polling interval to use if the server does not specify one, as
+/// described in RFC 8628 §3.2.
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// The lifetime in seconds of the device and user codes to assume if the
+/// server does not specify one, although RFC 8628 §3.2 requires it, so that
+/// polling does not continue forever.
+const DEFAULT_EXPIRES_IN: u64 = 30 * 60;
+
+/// The server's response to a device authorization request, defined in
+/// RFC 8628 §3.2.
+///
+/// The `user_code` and `verification_uri` should be shown to the user, and
+/// [`DeviceAuthorization::poll`] used to wait for them to authorize the
+/// device.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DeviceAuthorization {
+    data: Value,
+    received_at: Instant,
+}
+
+impl std::convert::TryFrom<Value> for DeviceAuthorization {
+    type Error = Error;
+
+    /// Construct a DeviceAuthorization from a [Value].
+    ///
+    /// Returns an [Error] if data is not a JSON Object, or the device_code,
+    /// user_code, or verification_uri is missing or not a string.
+    fn try_from(data: Value) -> Result<Self, Error> {
+        if !data.is_object() {
+            return Err(Error::new_from(
+                ErrorKind::ExchangeFailure,
+                String::from("DeviceAuthorization data was not an object"),
+            ));
+        }
+        for field in &["device_code", "user_code", "verification_uri"] {
+            match data.get(field) {
+                Some(val) if val.is_string() => (),
+                _ => {
+                    return Err(Error::new_from(
+                        ErrorKind::ExchangeFailure,
+                        format!("DeviceAuthorization {} was missing or not a string", field),
+                    ))
+                }
+            }
+        }
+
+        Ok(Self {
+            data,
+            received_at: Instant::now(),
+        })
+    }
+}
+
+impl DeviceAuthorization {
+    /// Get the DeviceAuthorization data as a raw JSON [Value]. It is
+    /// guaranteed to be of type Object.
+    pub fn as_value(&self) -> &Value {
+        &self.data
+    }
+
+    /// Get the device verification code, which is exchanged for a token.
+    pub fn device_code(&self) -> &str {
+        self.data
+            .get("device_code")
+            .and_then(Value::as_str)
+            .expect("device_code required at construction")
+    }
+
+    /// Get the code the user should enter at the verification URI.
+    pub fn user_code(&self) -> &str {
+        self.data
+            .get("user_code")
+            .and_then(Value::as_str)
+            .expect("user_code required at construction")
+    }
+
+    /// Get the URI the user should visit to authorize the device.
+    pub fn verification_uri(&self) -> &str {
+        self.data
+            .get("verification_uri")
+            .and_then(Value::as_str)
+            .expect("verification_uri required at construction")
+    }
+
+    /// Get a verification URI which includes the user code, if the server
+    /// provided one.
+    pub fn verification_uri_complete(&self) -> Option<&str> {
+        self.data
+            .get("verification_uri_complete")
+            .and_then(Value::as_str)
+    }
+
+    /// Get the lifetime in seconds of the device and user codes, if the
+    /// server provided one.
+    pub fn expires_in(&self) -> Option<i64> {
+        self.data.get("expires_in").and_then(Value::as_i64)
+    }
+
+    /// Get the minimum number of seconds to wait between polling requests.
+    /// This is 5 seconds if the server did not specify an interval.
+    pub fn interval(&self) -> u64 {
+        self.data
+            .get("interval")
+            .and_then(Value::as_u64)
+            .unwrap_or(DEFAULT_INTERVAL)
+    }
+
+    /// Poll the token endpoint until the user has authorized the device, as
+    /// described in RFC 8628 §3.4 and §3.5.
+    ///
+    /// This blocks the current thread, waiting [`interval`](Self::interval)
+    /// seconds between requests and backing off when the server responds
+    /// with `slow_down`. Returns the error from the server if the user
+    /// denies the request, or an error of kind [`ErrorKind::Timeout`] if the
+    /// codes expire first. If the server did not provide a lifetime, the
+    /// codes are assumed to expire after 30 minutes.
+    pub fn poll(
+        &self,
+        adapter: &dyn Adapter,
+        config: &OAuthConfig,
+    ) -> Result<TokenResponse, Error> {
+        let expires_in = self
+            .expires_in()
+            .map_or(DEFAULT_EXPIRES_IN, |secs| secs.max(0) as u64);
+        let deadline = self.received_at + Duration::from_secs(expires_in);
+        let mut interval = Duration::from_secs(self.interval());
+
+        loop {
+            thread::sleep(interval);
+            if Instant::now() >= deadline {
+                return Err(Error::new_from(
+                    ErrorKind::Timeout,
+                    String::from("device authorization expired"),
+                ));
+            }
+
+            let request = TokenRequest::DeviceCode(self.device_code().to_string());
+            let error = match adapter.exchange_code(config, request) {
+                Ok(token) => return Ok(token),
+                Err(error) => error,
+            };
+            match error.kind() {
+                ErrorKind::ExchangeError {
+                    error: Some(ErrorCode::AuthorizationPending),
+                    ..
+                } => {}
+                ErrorKind::ExchangeError {
+                    error: Some(ErrorCode::SlowDown),
+                    ..
+                } => {
+                    interval += Duration::from_secs(5);
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
@@ -1,5 +1,83 @@
 use std::error::Error as StdError;
 use std::fmt::{self, Display};
+use std::time::Duration;
+
+/// An `error` code returned by the server in an error response, as described
+/// in RFC 6749 §5.2 and RFC 8628 §3.5.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// `invalid_request`: the request is missing a parameter or is otherwise
+    /// malformed.
+    InvalidRequest,
+    /// `invalid_client`: client authentication failed.
+    InvalidClient,
+    /// `invalid_grant`: the authorization code or refresh token is invalid,
+    /// expired, or revoked. A stored refresh token which receives this error
+    /// should be discarded.
+    InvalidGrant,
+    /// `unauthorized_client`: the client is not authorized to use this grant
+    /// type.
+    UnauthorizedClient,
+    /// `unsupported_grant_type`: the server does not support this grant type.
+    UnsupportedGrantType,
+    /// `invalid_scope`: the requested scope is invalid or unknown.
+    InvalidScope,
+    /// `authorization_pending`: the user has not yet completed a device
+    /// authorization request.
+    AuthorizationPending,
+    /// `slow_down`: a device authorization request is being polled too
+    /// frequently.
+    SlowDown,
+    /// `access_denied`: the user denied a device authorization request.
+    AccessDenied,
+    /// `expired_token`: a device authorization request has expired.
+    ExpiredToken,
+    /// Any other error code.
+    Other(String),
+}
+
+impl ErrorCode {
+    /// Returns the error code as it appears in the `error` field.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidClient => "invalid_client",
+            ErrorCode::InvalidGrant => "invalid_grant",
+            ErrorCode::UnauthorizedClient => "unauthorized_client",
+            ErrorCode::UnsupportedGrantType => "unsupported_grant_type",
+            ErrorCode::InvalidScope => "invalid_scope",
+            ErrorCode::AuthorizationPending => "authorization_pending",
+            ErrorCode::SlowDown => "slow_down",
+            ErrorCode::AccessDenied => "access_denied",
+            ErrorCode::ExpiredToken => "expired_token",
+            ErrorCode::Other(code) => code,
+        }
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(code: &str) -> ErrorCode {
+        match code {
+            "invalid_request" => ErrorCode::InvalidRequest,
+            "invalid_client" => ErrorCode::InvalidClient,
+            "invalid_grant" => ErrorCode::InvalidGrant,
+            "unauthorized_client" => ErrorCode::UnauthorizedClient,
+            "unsupported_grant_type" => ErrorCode::UnsupportedGrantType,
+            "invalid_scope" => ErrorCode::InvalidScope,
+            "authorization_pending" => ErrorCode::AuthorizationPending,
+            "slow_down" => ErrorCode::SlowDown,
+            "access_denied" => ErrorCode::AccessDenied,
+            "expired_token" => ErrorCode::ExpiredToken,
+            code => ErrorCode::Other(code.to_string()),
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 /// Represents any kind of error that can occur during authorization.
 /// Most of these errors are returned by an [`Adapter`](super::Adapter).
@@ -8,29 +86,116 @@ pub enum ErrorKind {
     /// An error occurred during URI parsing or construction. This usually means
     /// the token exchange endpoint is incorrect. The attempted URI is included.
     InvalidUri(String),
+    /// A requested scope contains whitespace, for example because several
+    /// scopes were passed as a single string. The invalid scope is included.
+    InvalidScope(String),
     /// A token exchange request failed, for example because the server could
     /// not be reached, or the response body could not be parsed.
     ExchangeFailure,
+    /// A token exchange request did not complete before the configured
+    /// timeout.
+    Timeout,
+    /// A successful response to a token request was neither JSON nor
+    /// form-encoded, for example an HTML login page because the token
+    /// endpoint is misconfigured. The response's `Content-Type` is included.
+    UnexpectedContentType {
+        /// The `Content-Type` of the response.
+        content_type: String,
+    },
     /// A token exchange request errored (the response code indicated failure).
-    /// The response code is included.
-    ExchangeError(u16),
+    /// The response code is included, along with the error details if the
+    /// response body was an error response as described in RFC 6749 §5.2.
+    ExchangeError {
+        /// The response status code.
+        status: u16,
+        /// The `error` code given by the server.
+        error: Option<ErrorCode>,
+        /// The `error_description` given by the server.
+        description: Option<String>,
+        /// The `error_uri` given by the server.
+        uri: Option<String>,
+    },
+    /// The server rejected a request with `429 Too Many Requests`, and asked
+    /// for it to be retried after a delay with the `Retry-After` header.
+    RateLimited {
+        /// The time to wait before retrying the request.
+        retry_after: Duration,
+    },
+    /// The service provider's OpenID Connect discovery document or JSON Web
+    /// Key Set could not be retrieved, or was invalid.
+    DiscoveryFailure,
+    /// An OpenID Connect ID Token was missing, could not be decoded, or
+    /// failed verification.
+    InvalidIdToken,
+    /// The redirect handler received a callback without a state cookie, for
+    /// example because the user cleared their cookies or followed a
+    /// bookmarked callback URL.
+    StateCookieMissing,
+    /// The redirect handler received a callback with a valid state cookie,
+    /// but after the authorization it was issued for had expired, as set by
+    /// [`OAuthConfig::with_state_cookie_max_age`](crate::OAuthConfig::with_state_cookie_max_age).
+    StateExpired,
+    /// The `state` of a callback did not match the state cookie, or the
+    /// authorization was started for another client. This may indicate a
+    /// CSRF attempt.
+    StateMismatch,
+    /// The requested operation is not supported by the service provider or
+    /// the adapter, for example revoking a token when no revocation endpoint
+    /// is configured.
+    Unsupported,
     /// Another kind of error occurred.
     Other,
 }
 
+/// The network-level cause of an error of kind [`ErrorKind::ExchangeFailure`]
+/// or [`ErrorKind::Timeout`], for adapters which can tell. See
+/// [`Error::network_kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetworkErrorKind {
+    /// The host name could not be resolved.
+    Dns,
+    /// A connection to the server could not be established, for example
+    /// because it was refused.
+    Connect,
+    /// The TLS handshake failed, for example because the server's certificate
+    /// was not trusted.
+    Tls,
+    /// Another I/O error occurred, for example while the request was being
+    /// sent or the response received.
+    Io,
+}
+
 /// Represents an error during authorization. [`Error`] has a
 /// [`kind`](Error::kind) and a [`source`](std::error::Error::source)
 /// which describe the error.
+///
+/// The `Display` implementation describes the kind of error, including the
+/// status code and error details of an [`ErrorKind::ExchangeError`], followed
+/// by the source error, if any:
+///
+/// ```rust
+/// use std::error::Error as _;
+/// use rocket_oauth2::{Error, ErrorKind};
+///
+/// let error = Error::new_from(ErrorKind::ExchangeFailure, "connection reset");
+/// assert_eq!(error.to_string(), "failed to exchange token: connection reset");
+/// assert_eq!(error.source().unwrap().to_string(), "connection reset");
+/// ```
 #[derive(Debug)]
 pub struct Error {
     kind: ErrorKind,
     source: Option<Box<dyn StdError + Send + Sync>>,
+    network_kind: Option<NetworkErrorKind>,
 }
 
 impl Error {
     /// Create a new `Error` with no source.
     pub fn new(kind: ErrorKind) -> Self {
-        Self { kind, source: None }
+        Self {
+            kind,
+            source: None,
+            network_kind: None,
+        }
     }
 
     /// Create a new `Error` given a `kind` and `source`.
@@ -41,25 +206,76 @@ impl Error {
         Self {
             kind,
             source: Some(source.into()),
+            network_kind: None,
         }
     }
 
+    /// Sets the network-level cause of the error.
+    pub fn with_network_kind(mut self, network_kind: NetworkErrorKind) -> Self {
+        self.network_kind = Some(network_kind);
+        self
+    }
+
     /// Returns the kind of error that occurred.
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// Returns the network-level cause of the error, such as a DNS or TLS
+    /// failure, if it is known. `HyperSyncRustlsAdapter` sets it for every
+    /// request which fails without a response, including OpenID Connect
+    /// discovery and key set requests; `ReqwestAdapter` only for connection
+    /// failures, and not for discovery or key set requests.
+    ///
+    /// The underlying error is also available from
+    /// [`source`](std::error::Error::source).
+    pub fn network_kind(&self) -> Option<NetworkErrorKind> {
+        self.network_kind
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self.kind {
             ErrorKind::InvalidUri(uri) => write!(f, "invalid URI: '{}'", uri)?,
+            ErrorKind::InvalidScope(scope) => {
+                write!(f, "invalid scope (contains whitespace): '{}'", scope)?
+            }
             ErrorKind::ExchangeFailure => write!(f, "failed to exchange token")?,
-            ErrorKind::ExchangeError(code) => write!(
+            ErrorKind::Timeout => write!(f, "token exchange timed out")?,
+            ErrorKind::UnexpectedContentType { content_type } => write!(
+                f,
+                "token endpoint responded with unexpected content type '{}'",
+                content_type
+            )?,
+            ErrorKind::ExchangeError {
+                status,
+                error,
+                description,
+                ..
+            } => {
+                write!(
+                    f,
+                    "token exchange returned non-success status code: {}",
+                    status
+                )?;
+                match (error, description) {
+                    (Some(error), Some(description)) => write!(f, " ({}: {})", error, description)?,
+                    (Some(error), None) => write!(f, " ({})", error)?,
+                    _ => (),
+                }
+            }
+            ErrorKind::RateLimited { retry_after } => write!(
                 f,
-                "token exchange returned non-success status code: {}",
-                code
+                "rate limited by the server; retry after {} seconds",
+                retry_after.as_secs()
             )?,
+            ErrorKind::DiscoveryFailure => write!(f, "failed to discover provider metadata")?,
+            ErrorKind::InvalidIdToken => write!(f, "invalid ID token")?,
+            ErrorKind::StateCookieMissing => write!(f, "state cookie missing")?,
+            ErrorKind::StateExpired => write!(f, "authorization request expired")?,
+            ErrorKind::StateMismatch => write!(f, "state mismatch")?,
+            ErrorKind::Unsupported => write!(f, "operation not supported")?,
             ErrorKind::Other => write!(f, "an unknown error occurred")?,
         }
 
@@ -0,0 +1,249 @@
+//! [Adapter] implemented on top of an HTTP client provided by the application.
+
+use rocket::http::uri::Absolute;
+use rocket::http::Method;
+use serde_json::Value;
+
+use super::{
+    Adapter, DeviceAuthorization, DiscoveredProvider, Error, Introspection, OAuthConfig,
+    TokenRequest, TokenResponse, TokenTypeHint,
+};
+use crate::adapter_util::{self, TokenRequestBody};
+use crate::request_util;
+
+/// An HTTP request to be sent by an [`HttpSend`] implementation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpRequest {
+    /// The request method; either `GET` or `POST`.
+    pub method: Method,
+    /// The absolute URI to send the request to.
+    pub uri: String,
+    /// The request headers, such as `Accept`, `Content-Type`, and
+    /// `Authorization`.
+    pub headers: Vec<(String, String)>,
+    /// The request body, if any. `POST` requests are always form-encoded.
+    pub body: Option<String>,
+}
+
+/// The response to an [`HttpRequest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// The response status code.
+    pub status: u16,
+    /// The response headers. Only `Content-Type` and `Retry-After` are
+    /// currently used.
+    pub headers: Vec<(String, String)>,
+    /// The response body.
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Gets the value of the first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A minimal HTTP client, used by [`HttpClientAdapter`] to send requests.
+///
+/// Implementations only need to send the request and return the response.
+/// They should return an error of kind [`ErrorKind::Timeout`](crate::ErrorKind::Timeout)
+/// if the request timed out, and [`ErrorKind::ExchangeFailure`](crate::ErrorKind::ExchangeFailure)
+/// if it could not be sent at all; responses with error status codes are
+/// not errors.
+pub trait HttpSend: Send + Sync + 'static {
+    /// Send `request` and return the response.
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error>;
+}
+
+/// `Adapter` implementation that sends requests with any [`HttpSend`]
+/// implementation, such as a client with mutual TLS or a different HTTP
+/// library.
+///
+/// The requests and responses are handled exactly as in the built-in
+/// adapters. With the `hyper_sync_rustls_adapter` feature,
+/// [`HyperSyncRustlsAdapter`](crate::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter)
+/// implements `HttpSend`, so `HttpClientAdapter::<HyperSyncRustlsAdapter>::default()`
+/// behaves like the default adapter.
+///
+/// ```rust
+/// # extern crate rocket_oauth2;
+/// use rocket_oauth2::http_client_adapter::{
+///     HttpClientAdapter, HttpRequest, HttpResponse, HttpSend,
+/// };
+/// use rocket_oauth2::Error;
+///
+/// struct MyClient;
+///
+/// impl HttpSend for MyClient {
+///     fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+///         // Send the request with the HTTP client of your choice
+///         # unimplemented!()
+///     }
+/// }
+///
+/// let adapter = HttpClientAdapter::new(MyClient);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct HttpClientAdapter<H> {
+    client: H,
+    headers: Vec<(String, String)>,
+    accept: Option<String>,
+}
+
+impl<H: HttpSend> HttpClientAdapter<H> {
+    /// Create a new `HttpClientAdapter` that will use `client` to send
+    /// requests.
+    pub fn new(client: H) -> HttpClientAdapter<H> {
+        HttpClientAdapter {
+            client,
+            headers: Vec::new(),
+            accept: None,
+        }
+    }
+
+    /// Sets the `Accept` header sent with requests to the token endpoint, and
+    /// the revocation, introspection, and device authorization endpoints.
+    /// The default is `application/json`, but some providers require a
+    /// provider-specific value before they respond with JSON.
+    pub fn with_accept(mut self, accept: String) -> HttpClientAdapter<H> {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Adds a header to every request made to the service provider, for
+    /// example an API key required by a gateway in front of it. The
+    /// `Accept`, `Content-Type`, and `Authorization` headers set by the
+    /// adapter are sent after, and take precedence over, headers with the
+    /// same name.
+    pub fn with_header(mut self, name: String, value: String) -> HttpClientAdapter<H> {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Send `request` with the additional headers.
+    fn send(&self, mut request: HttpRequest) -> Result<HttpResponse, Error> {
+        let mut headers = self.headers.clone();
+        headers.append(&mut request.headers);
+        request.headers = headers;
+        self.client.send(request)
+    }
+
+    /// Gets the client used to send requests.
+    pub fn client(&self) -> &H {
+        &self.client
+    }
+
+    /// Send a form-encoded POST request to `uri`.
+    fn post(&self, uri: &str, req: TokenRequestBody) -> Result<HttpResponse, Error> {
+        let accept = self.accept.as_deref().unwrap_or("application/json");
+        let response = self.send(form_post(uri, accept, req))?;
+        adapter_util::check_rate_limit(response.status, response.header("Retry-After"))?;
+        Ok(response)
+    }
+}
+
+/// Build a form-encoded POST request to `uri` with the given `Accept` header
+/// and body.
+pub(crate) fn form_post(uri: &str, accept: &str, req: TokenRequestBody) -> HttpRequest {
+    let mut headers = vec![
+        (String::from("Accept"), accept.to_string()),
+        (
+            String::from("Content-Type"),
+            String::from("application/x-www-form-urlencoded"),
+        ),
+    ];
+    if let Some(authorization) = req.authorization {
+        headers.push((String::from("Authorization"), authorization));
+    }
+
+    HttpRequest {
+        method: Method::Post,
+        uri: uri.to_string(),
+        headers,
+        body: Some(req.body),
+    }
+}
+
+impl<H: HttpSend> Adapter for HttpClientAdapter<H> {
+    fn authorization_uri(
+        &self,
+        config: &OAuthConfig,
+        state: &str,
+        scopes: &[&str],
+        extra_params: &[(&str, &str)],
+    ) -> Result<Absolute<'static>, Error> {
+        request_util::authorization_uri(config, state, scopes, extra_params)
+    }
+
+    fn exchange_code(
+        &self,
+        config: &OAuthConfig,
+        token: TokenRequest,
+    ) -> Result<TokenResponse, Error> {
+        let req = adapter_util::token_request_body(config, token)?;
+        let response = self.post(config.provider().token_uri().as_ref(), req)?;
+
+        adapter_util::token_response(
+            response.status,
+            response.header("Content-Type"),
+            response.header("Date"),
+            &response.body[..],
+        )
+    }
+
+    fn discover(&self, issuer: &str) -> Result<DiscoveredProvider, Error> {
+        let response = self.send(HttpRequest {
+            method: Method::Get,
+            uri: adapter_util::discovery_uri(issuer),
+            headers: vec![(String::from("Accept"), String::from("application/json"))],
+            body: None,
+        })?;
+
+        adapter_util::discovery_response(issuer, response.status, &response.body[..])
+    }
+
+    fn fetch_jwks(&self, jwks_uri: &str) -> Result<Value, Error> {
+        let response = self.send(HttpRequest {
+            method: Method::Get,
+            uri: jwks_uri.to_string(),
+            headers: vec![(String::from("Accept"), String::from("application/json"))],
+            body: None,
+        })?;
+
+        adapter_util::jwks_response(response.status, &response.body[..])
+    }
+
+    fn revoke(
+        &self,
+        config: &OAuthConfig,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), Error> {
+        let (uri, req) = adapter_util::revocation_request_body(config, token, token_type_hint)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::revocation_response(response.status, &response.body[..])
+    }
+
+    fn introspect(&self, config: &OAuthConfig, token: &str) -> Result<Introspection, Error> {
+        let (uri, req) = adapter_util::introspection_request_body(config, token)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::introspection_response(response.status, &response.body[..])
+    }
+
+    fn device_authorization(
+        &self,
+        config: &OAuthConfig,
+        scopes: &[&str],
+    ) -> Result<DeviceAuthorization, Error> {
+        let (uri, req) = adapter_util::device_authorization_request_body(config, scopes)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::device_authorization_response(response.status, &response.body[..])
+    }
+}
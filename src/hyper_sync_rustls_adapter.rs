@@ -3,24 +3,951 @@
 use hyper;
 use hyper_sync_rustls;
 
-use std::convert::TryInto;
-use std::io::Read;
+use std::env;
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use rocket::http::ext::IntoOwned;
-use rocket::http::uri::Absolute;
-use url::form_urlencoded::Serializer as UrlSerializer;
-use url::Url;
+use ring::digest;
+
+use rocket::http::{uri::Absolute, Method};
+use serde_json::Value;
 
 use self::hyper::{
-    header::{Accept, ContentType},
-    net::HttpsConnector,
+    client::{Pool, ProxyConfig, Response},
+    header::{Accept, Headers, UserAgent},
+    net::{HttpStream, HttpsConnector, NetworkConnector, NetworkStream},
     Client,
 };
-use super::{Adapter, Error, ErrorKind, OAuthConfig, TokenRequest, TokenResponse};
+use super::{
+    Adapter, DeviceAuthorization, DiscoveredProvider, Error, ErrorKind, Introspection,
+    NetworkErrorKind, OAuthConfig, TokenRequest, TokenResponse, TokenTypeHint,
+};
+use crate::adapter_util::{self, TokenRequestBody};
+use crate::http_client_adapter::{self, HttpRequest, HttpResponse, HttpSend};
+use crate::request_util;
 
 /// `Adapter` implementation that uses `hyper` and `rustls` to perform the token exchange.
+///
+/// Connections are pooled and reused across token exchanges, including by
+/// clones of the adapter.
+///
+/// Requests are made directly to the server unless an HTTP proxy is
+/// configured with [`with_proxy`](HyperSyncRustlsAdapter::with_proxy) or
+/// [`with_proxy_from_env`](HyperSyncRustlsAdapter::with_proxy_from_env).
+///
+/// A client certificate for mutual TLS can be configured with
+/// [`with_client_certificate`](HyperSyncRustlsAdapter::with_client_certificate)
+/// or [`with_client_certificate_pem`](HyperSyncRustlsAdapter::with_client_certificate_pem),
+/// and the servers' public keys can be pinned with
+/// [`with_pinned_public_keys`](HyperSyncRustlsAdapter::with_pinned_public_keys).
+///
+/// `HyperSyncRustlsAdapter` also implements [`HttpSend`], so it can be used
+/// as the client of an
+/// [`HttpClientAdapter`](crate::http_client_adapter::HttpClientAdapter).
 #[derive(Clone, Debug)]
-pub struct HyperSyncRustlsAdapter;
+pub struct HyperSyncRustlsAdapter {
+    client: Arc<Mutex<Option<Arc<Client>>>>,
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    resolver: Option<Resolver>,
+    proxy: Option<(String, u16)>,
+    max_attempts: u32,
+    retry_delay: Duration,
+    retry_policy: Option<RetryPolicy>,
+    user_agent: String,
+    accept: Option<String>,
+    headers: Vec<(String, String)>,
+    client_certificate: Option<ClientCertificate>,
+    pinned_keys: Vec<Vec<u8>>,
+}
+
+/// Decides whether to retry after a failed attempt, given the number of the
+/// attempt and its error, by returning the delay before the next attempt.
+type RetryPolicyFn = dyn Fn(u32, &Error) -> Option<Duration> + Send + Sync;
+
+/// A retry policy set with
+/// [`with_retry_policy`](HyperSyncRustlsAdapter::with_retry_policy).
+#[derive(Clone)]
+struct RetryPolicy(Arc<RetryPolicyFn>);
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RetryPolicy").field(&(..)).finish()
+    }
+}
+
+/// Resolves a host and port to the addresses to connect to, in order.
+type ResolveFn = dyn Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync;
+
+/// A custom name resolver set with
+/// [`with_resolver`](HyperSyncRustlsAdapter::with_resolver).
+#[derive(Clone)]
+struct Resolver(Arc<ResolveFn>);
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Resolver").field(&(..)).finish()
+    }
+}
+
+/// Connects over plain TCP like hyper's `HttpConnector`, with an optional
+/// timeout for establishing the connection and an optional custom resolver.
+#[derive(Clone)]
+struct Connector {
+    timeout: Option<Duration>,
+    resolver: Option<Resolver>,
+}
+
+impl NetworkConnector for Connector {
+    type Stream = HttpStream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<HttpStream> {
+        if scheme != "http" {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "Invalid scheme for Http");
+            return Err(hyper::Error::Io(e));
+        }
+        let addrs = match &self.resolver {
+            Some(resolver) => (resolver.0)(host, port),
+            None => (host, port).to_socket_addrs().map(Iterator::collect),
+        };
+        let addrs: Vec<SocketAddr> =
+            addrs.map_err(|e| ConnectorError::wrap(NetworkErrorKind::Dns, e))?;
+        if addrs.is_empty() {
+            let e = io::Error::new(io::ErrorKind::InvalidInput, "could not resolve host");
+            return Err(ConnectorError::wrap(NetworkErrorKind::Dns, e).into());
+        }
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => {
+                return TcpStream::connect(&addrs[..])
+                    .map(HttpStream)
+                    .map_err(|e| ConnectorError::wrap(NetworkErrorKind::Connect, e).into())
+            }
+        };
+
+        // Try each resolved address in turn, as `TcpStream::connect` does.
+        let mut last_error = None;
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(HttpStream(stream)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let e = last_error.expect("at least one address was tried");
+        Err(ConnectorError::wrap(NetworkErrorKind::Connect, e).into())
+    }
+}
+
+/// An I/O error from the [`Connector`], marked as a name resolution or
+/// connection failure so that it can be classified by [`network_kind`].
+#[derive(Debug)]
+struct ConnectorError {
+    kind: NetworkErrorKind,
+    source: io::Error,
+}
+
+impl ConnectorError {
+    /// Wrap `source` in an `io::Error` of the same kind, so that timeouts are
+    /// still recognized.
+    fn wrap(kind: NetworkErrorKind, source: io::Error) -> io::Error {
+        io::Error::new(source.kind(), ConnectorError { kind, source })
+    }
+}
+
+impl fmt::Display for ConnectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+impl std::error::Error for ConnectorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Classify the cause of a failed request.
+fn network_kind(e: &hyper::Error) -> NetworkErrorKind {
+    match e {
+        hyper::Error::Ssl(_) => NetworkErrorKind::Tls,
+        hyper::Error::Io(io) => match io.get_ref() {
+            Some(inner) => match inner.downcast_ref::<ConnectorError>() {
+                Some(e) => e.kind,
+                None if inner.is::<rustls::TLSError>() => NetworkErrorKind::Tls,
+                None => NetworkErrorKind::Io,
+            },
+            None => NetworkErrorKind::Io,
+        },
+        _ => NetworkErrorKind::Io,
+    }
+}
+
+/// How long an idle connection is kept for reuse. This is shorter than the
+/// keep-alive timeout of most servers, so that connections they have already
+/// closed are rarely reused.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns true if the server has closed `stream`, or sent data on it which
+/// was not asked for, so that it cannot be reused.
+///
+/// This reads from `stream` with a very short timeout, and then restores the
+/// read timeout to `read_timeout`.
+fn closed_by_peer<S: Read + NetworkStream>(stream: &mut S, read_timeout: Duration) -> bool {
+    if stream
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .is_err()
+    {
+        return true;
+    }
+    let closed = !matches!(
+        stream.read(&mut [0; 1]),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+    );
+    closed || stream.set_read_timeout(Some(read_timeout)).is_err()
+}
+
+/// The longest delay between retries with exponential backoff, unless the
+/// base delay is longer, and the longest `Retry-After` which is waited for.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Verifies server certificates like rustls' default verifier, and then
+/// checks that the end-entity certificate has a pinned public key.
+///
+/// Only the end-entity certificate is checked, because webpki does not
+/// expose the path it validated: a pinned certificate anywhere else in the
+/// presented chain might not be on that path at all.
+struct PinningVerifier {
+    pinned_keys: Vec<Vec<u8>>,
+}
+
+/// The signature algorithms accepted in server certificates, which are the
+/// same as rustls' default.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA1,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
+impl rustls::ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let (end_entity, intermediates) = match presented_certs.split_first() {
+            Some(certs) => certs,
+            None => return Err(rustls::TLSError::NoCertificatesPresented),
+        };
+        let cert = webpki::EndEntityCert::from(untrusted::Input::from(&end_entity.0))
+            .map_err(rustls::TLSError::WebPKIError)?;
+        let chain: Vec<_> = intermediates
+            .iter()
+            .map(|cert| untrusted::Input::from(&cert.0))
+            .collect();
+        let anchors: Vec<_> = roots.roots.iter().map(|r| r.to_trust_anchor()).collect();
+        let now = webpki::Time::try_from(SystemTime::now())
+            .map_err(|_| rustls::TLSError::FailedToGetCurrentTime)?;
+
+        cert.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TLSServerTrustAnchors(&anchors),
+            &chain,
+            now,
+        )
+        .map_err(rustls::TLSError::WebPKIError)?;
+        cert.verify_is_valid_for_dns_name(dns_name)
+            .map_err(rustls::TLSError::WebPKIError)?;
+
+        let pinned = match subject_public_key_info(&end_entity.0) {
+            Some(spki) => {
+                let hash = digest::digest(&digest::SHA256, spki);
+                self.pinned_keys.iter().any(|pin| pin[..] == *hash.as_ref())
+            }
+            None => false,
+        };
+        if !pinned {
+            return Err(rustls::TLSError::General(String::from(
+                "the server's certificate does not have a pinned public key",
+            )));
+        }
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// Get the DER-encoded SubjectPublicKeyInfo of a DER-encoded X.509
+/// certificate, as described by RFC 5280 §4.1.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (_, certificate, _) = der_element(cert, 0x30)?;
+    let (_, mut tbs, _) = der_element(certificate, 0x30)?;
+    // The version is optional, and explicitly tagged.
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs, 0xa0)?.2;
+    }
+    // serialNumber, signature, issuer, validity, and subject.
+    for &tag in &[0x02, 0x30, 0x30, 0x30, 0x30] {
+        tbs = der_element(tbs, tag)?.2;
+    }
+    let (spki, _, _) = der_element(tbs, 0x30)?;
+    Some(spki)
+}
+
+/// Read a DER element with the given tag from the start of `input`,
+/// returning the whole element, its contents, and the remaining input.
+fn der_element(input: &[u8], tag: u8) -> Option<(&[u8], &[u8], &[u8])> {
+    if *input.first()? != tag {
+        return None;
+    }
+    let first = *input.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 3 {
+            return None;
+        }
+        let bytes = input.get(2..2 + n)?;
+        let len = bytes.iter().fold(0, |len, &b| (len << 8) | b as usize);
+        (len, 2 + n)
+    };
+    let end = header.checked_add(len)?;
+    let element = input.get(..end)?;
+    Some((element, &element[header..], &input[end..]))
+}
+
+/// A certificate chain and private key presented to servers which request
+/// client authentication.
+#[derive(Clone)]
+struct ClientCertificate {
+    chain: Vec<rustls::Certificate>,
+    key: rustls::PrivateKey,
+}
+
+impl fmt::Debug for ClientCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The private key is deliberately omitted.
+        f.debug_struct("ClientCertificate")
+            .field("chain_len", &self.chain.len())
+            .finish()
+    }
+}
+
+impl Default for HyperSyncRustlsAdapter {
+    fn default() -> HyperSyncRustlsAdapter {
+        HyperSyncRustlsAdapter {
+            client: Default::default(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: None,
+            read_timeout: None,
+            resolver: None,
+            proxy: None,
+            max_attempts: 1,
+            retry_delay: Duration::from_millis(500),
+            retry_policy: None,
+            user_agent: String::from(adapter_util::DEFAULT_USER_AGENT),
+            accept: None,
+            headers: Vec::new(),
+            client_certificate: None,
+            pinned_keys: Vec::new(),
+        }
+    }
+}
+
+impl HyperSyncRustlsAdapter {
+    /// Create a new `HyperSyncRustlsAdapter`.
+    pub fn new() -> HyperSyncRustlsAdapter {
+        HyperSyncRustlsAdapter::default()
+    }
+
+    /// Sets the timeout for reading from and writing to the connection to
+    /// the server. The default timeout is 30 seconds.
+    ///
+    /// The timeout applies to each read and write, so a server which sends
+    /// its response slowly can take longer. It also bounds retries: no retry
+    /// is started once `timeout` has passed since the first attempt, or if
+    /// the delay before it would end after that; the last error or response
+    /// is returned instead.
+    pub fn with_timeout(mut self, timeout: Duration) -> HyperSyncRustlsAdapter {
+        self.timeout = timeout;
+        self.client = Default::default();
+        self
+    }
+
+    /// Sets the timeout for establishing a connection to the server, or to
+    /// the proxy if one is configured. By default, the operating system's
+    /// timeout applies.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> HyperSyncRustlsAdapter {
+        self.connect_timeout = Some(timeout);
+        self.client = Default::default();
+        self
+    }
+
+    /// Resolves host names with `resolver` instead of the system resolver,
+    /// for example to use a particular DNS server. The resolver returns the
+    /// addresses for a host and port, which are tried in the order given
+    /// until a connection succeeds, so it also controls whether IPv6 or IPv4
+    /// addresses are preferred. If a proxy is configured, only the proxy's
+    /// host is resolved.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// use std::net::{SocketAddr, ToSocketAddrs};
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    ///
+    /// // Prefer IPv6 addresses.
+    /// let adapter = HyperSyncRustlsAdapter::new().with_resolver(|host, port| {
+    ///     let mut addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+    ///     addrs.sort_by_key(|addr| !addr.is_ipv6());
+    ///     Ok(addrs)
+    /// });
+    /// ```
+    pub fn with_resolver<R>(mut self, resolver: R) -> HyperSyncRustlsAdapter
+    where
+        R: Fn(&str, u16) -> io::Result<Vec<SocketAddr>> + Send + Sync + 'static,
+    {
+        self.resolver = Some(Resolver(Arc::new(resolver)));
+        self.client = Default::default();
+        self
+    }
+
+    /// Sets the timeout for reading from the connection to the server,
+    /// including during the TLS handshake, separately from the timeout set
+    /// with [`with_timeout`](HyperSyncRustlsAdapter::with_timeout), which then
+    /// only applies to writes.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> HyperSyncRustlsAdapter {
+        self.read_timeout = Some(timeout);
+        self.client = Default::default();
+        self
+    }
+
+    /// Retries requests which fail to connect or receive a `5xx` response,
+    /// making at most `max_attempts` attempts in total. The delay before each
+    /// retry starts at `base_delay` and doubles with every attempt, up to a
+    /// minute or `base_delay`, whichever is longer. Retries also stop once
+    /// the timeout set with
+    /// [`with_timeout`](HyperSyncRustlsAdapter::with_timeout) has passed.
+    ///
+    /// `429 Too Many Requests` responses with a `Retry-After` header are also
+    /// retried, waiting at least as long as the server asked. If no attempts
+    /// remain, or the server asked to wait for more than a minute, they
+    /// result in an error of kind
+    /// [`ErrorKind::RateLimited`](crate::ErrorKind::RateLimited) at once.
+    ///
+    /// By default requests are not retried. Other responses with `4xx` status
+    /// codes are never retried, and neither are errors after the request may
+    /// have been sent, such as timeouts.
+    ///
+    /// Token requests which redeem a single-use credential, such as an
+    /// authorization code, refresh token, or device code, are only retried if
+    /// the connection to the server could not be established; a `5xx` or
+    /// `429` response is returned as it is, because the server may already
+    /// have consumed the credential. Requests sent through the [`HttpSend`]
+    /// implementation are treated the same way, except for `GET` requests.
+    pub fn with_retries(
+        mut self,
+        max_attempts: u32,
+        base_delay: Duration,
+    ) -> HyperSyncRustlsAdapter {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_delay = base_delay;
+        self
+    }
+
+    /// Decides whether to retry failed requests with `policy`, replacing
+    /// [`with_retries`](HyperSyncRustlsAdapter::with_retries).
+    ///
+    /// After each failed attempt, `policy` is called with the number of the
+    /// attempt, starting at 1, and the error. It returns the delay before the
+    /// next attempt, or `None` to give up and return the error. Connection
+    /// errors and timeouts are passed as they are; `5xx` responses are passed
+    /// as errors of kind [`ErrorKind::ExchangeError`] with the status code,
+    /// and `429 Too Many Requests` responses with a `Retry-After` header as
+    /// errors of kind [`ErrorKind::RateLimited`]. Other responses are not
+    /// retried. As with `with_retries`, requests which redeem a single-use
+    /// credential are only passed to `policy` if the connection to the server
+    /// could not be established.
+    ///
+    /// ```rust
+    /// # extern crate rocket_oauth2;
+    /// use std::time::Duration;
+    ///
+    /// use rocket_oauth2::hyper_sync_rustls_adapter::HyperSyncRustlsAdapter;
+    /// use rocket_oauth2::ErrorKind;
+    ///
+    /// // Retry timeouts and rate limits up to twice, and nothing else.
+    /// let adapter = HyperSyncRustlsAdapter::new().with_retry_policy(|attempt, error| {
+    ///     match error.kind() {
+    ///         _ if attempt > 2 => None,
+    ///         ErrorKind::Timeout => Some(Duration::from_secs(1)),
+    ///         ErrorKind::RateLimited { retry_after } => Some(*retry_after),
+    ///         _ => None,
+    ///     }
+    /// });
+    /// ```
+    pub fn with_retry_policy<F>(mut self, policy: F) -> HyperSyncRustlsAdapter
+    where
+        F: Fn(u32, &Error) -> Option<Duration> + Send + Sync + 'static,
+    {
+        self.retry_policy = Some(RetryPolicy(Arc::new(policy)));
+        self
+    }
+
+    /// Sets the `User-Agent` header sent with every request. The default is
+    /// `rocket_oauth2/<version>`.
+    pub fn with_user_agent(mut self, user_agent: String) -> HyperSyncRustlsAdapter {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Sets the `Accept` header sent with requests to the token endpoint, and
+    /// the revocation, introspection, and device authorization endpoints.
+    /// The default is `application/json`, but some providers require a
+    /// provider-specific value before they respond with JSON.
+    pub fn with_accept(mut self, accept: String) -> HyperSyncRustlsAdapter {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Adds a header to every request made to the service provider, for
+    /// example an API key required by a gateway in front of it. The
+    /// `Accept`, `Content-Type`, and `Authorization` headers set by the
+    /// adapter take precedence over headers with the same name.
+    ///
+    /// These headers are not sent by the user's browser, so they cannot be
+    /// added to the authorization request.
+    pub fn with_header(mut self, name: String, value: String) -> HyperSyncRustlsAdapter {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// Presents a client certificate during the TLS handshake with servers
+    /// which request one, for mutual TLS client authentication (RFC 8705).
+    ///
+    /// `cert_chain` is a list of DER-encoded X.509 certificates, starting
+    /// with the client's certificate, and `key` is the DER-encoded PKCS#8 or
+    /// PKCS#1 private key for it. Returns an error if the key is not a
+    /// supported RSA or ECDSA key.
+    ///
+    /// The certificate is independent of the configured `client_secret`, and
+    /// of the [`ClientAuthentication`](crate::ClientAuthentication) method,
+    /// which still apply to every request.
+    pub fn with_client_certificate(
+        mut self,
+        cert_chain: Vec<Vec<u8>>,
+        key: Vec<u8>,
+    ) -> Result<HyperSyncRustlsAdapter, Error> {
+        let key = rustls::PrivateKey(key);
+        if rustls::sign::any_supported_type(&key).is_err() {
+            return Err(Error::new_from(
+                ErrorKind::Other,
+                String::from("unsupported client certificate private key"),
+            ));
+        }
+
+        self.client_certificate = Some(ClientCertificate {
+            chain: cert_chain.into_iter().map(rustls::Certificate).collect(),
+            key,
+        });
+        self.client = Default::default();
+        Ok(self)
+    }
+
+    /// Presents a client certificate during the TLS handshake, like
+    /// [`with_client_certificate`](HyperSyncRustlsAdapter::with_client_certificate),
+    /// but reads the certificate chain and private key from PEM.
+    ///
+    /// `key` must contain a single `PRIVATE KEY` (PKCS#8) or
+    /// `RSA PRIVATE KEY` (PKCS#1) block.
+    pub fn with_client_certificate_pem(
+        self,
+        cert_chain: &[u8],
+        key: &[u8],
+    ) -> Result<HyperSyncRustlsAdapter, Error> {
+        let invalid = |what: &str| Error::new_from(ErrorKind::Other, format!("invalid {}", what));
+
+        let chain = rustls::internal::pemfile::certs(&mut &cert_chain[..])
+            .map_err(|_| invalid("client certificate chain"))?;
+        if chain.is_empty() {
+            return Err(invalid("client certificate chain"));
+        }
+
+        let mut keys = rustls::internal::pemfile::pkcs8_private_keys(&mut &key[..])
+            .map_err(|_| invalid("client certificate private key"))?;
+        if keys.is_empty() {
+            keys = rustls::internal::pemfile::rsa_private_keys(&mut &key[..])
+                .map_err(|_| invalid("client certificate private key"))?;
+        }
+        if keys.len() != 1 {
+            return Err(invalid("client certificate private key"));
+        }
+
+        self.with_client_certificate(
+            chain.into_iter().map(|cert| cert.0).collect(),
+            keys.remove(0).0,
+        )
+    }
+
+    /// Pins the public keys of the servers' certificates. A TLS connection is
+    /// only established if the server's certificate chain is valid as usual,
+    /// and the server's own (end-entity) certificate has a pinned public key.
+    /// Keys of intermediate and root certificates cannot be pinned. This
+    /// protects against a compromised certificate authority issuing a
+    /// certificate for the service provider's domain.
+    ///
+    /// Each pin is the base64-encoded SHA-256 hash of a DER-encoded
+    /// SubjectPublicKeyInfo, as used by HTTP Public Key Pinning. It can be
+    /// computed with:
+    ///
+    /// ```text
+    /// openssl x509 -in cert.pem -pubkey -noout | openssl pkey -pubin -outform DER |
+    ///     openssl dgst -sha256 -binary | base64
+    /// ```
+    ///
+    /// The pins apply to every server the adapter connects to, so a pin for
+    /// a backup key should be included to allow the provider to rotate keys.
+    pub fn with_pinned_public_keys(
+        mut self,
+        pins: Vec<String>,
+    ) -> Result<HyperSyncRustlsAdapter, Error> {
+        let mut pinned_keys = Vec::with_capacity(pins.len());
+        for pin in pins {
+            match base64::decode(&pin) {
+                Ok(hash) if hash.len() == digest::SHA256_OUTPUT_LEN => pinned_keys.push(hash),
+                _ => {
+                    return Err(Error::new_from(
+                        ErrorKind::Other,
+                        format!("invalid public key pin '{}'", pin),
+                    ))
+                }
+            }
+        }
+
+        self.pinned_keys = pinned_keys;
+        self.client = Default::default();
+        Ok(self)
+    }
+
+    /// Sends all requests through the HTTP proxy at `host` and `port`. HTTPS
+    /// requests are tunneled through the proxy with `CONNECT`.
+    pub fn with_proxy(mut self, host: String, port: u16) -> HyperSyncRustlsAdapter {
+        self.proxy = Some((host, port));
+        self.client = Default::default();
+        self
+    }
+
+    /// Sends all requests through the HTTP proxy given by the `HTTPS_PROXY`
+    /// or `https_proxy` environment variable, if either is set.
+    ///
+    /// Only `http://` proxy URLs are supported, and `NO_PROXY` is not
+    /// consulted. An unsupported or invalid proxy URL is logged and ignored.
+    pub fn with_proxy_from_env(self) -> HyperSyncRustlsAdapter {
+        let proxy = match env::var("HTTPS_PROXY").or_else(|_| env::var("https_proxy")) {
+            Ok(proxy) => proxy,
+            Err(_) => return self,
+        };
+
+        match url::Url::parse(&proxy) {
+            Ok(ref url) if url.scheme() == "http" && url.host_str().is_some() => {
+                let host = url.host_str().expect("host is present").to_string();
+                let port = url.port_or_known_default().unwrap_or(80);
+                self.with_proxy(host, port)
+            }
+            _ => {
+                log::warn!("Ignoring unsupported HTTPS_PROXY '{}'", proxy);
+                self
+            }
+        }
+    }
+
+    /// Returns the shared `Client`, creating it if this is the first request.
+    fn client(&self) -> Arc<Client> {
+        let mut client = self.client.lock().unwrap_or_else(|e| e.into_inner());
+        client
+            .get_or_insert_with(|| {
+                let mut tls = hyper_sync_rustls::TlsClient::new();
+                if let Some(certificate) = &self.client_certificate {
+                    Arc::make_mut(&mut tls.cfg)
+                        .set_single_client_cert(certificate.chain.clone(), certificate.key.clone());
+                }
+                if !self.pinned_keys.is_empty() {
+                    let verifier = PinningVerifier {
+                        pinned_keys: self.pinned_keys.clone(),
+                    };
+                    Arc::make_mut(&mut tls.cfg)
+                        .dangerous()
+                        .set_certificate_verifier(Arc::new(verifier));
+                }
+                let connector = Connector {
+                    timeout: self.connect_timeout,
+                    resolver: self.resolver.clone(),
+                };
+                let read_timeout = self.read_timeout.unwrap_or(self.timeout);
+                let mut client = match &self.proxy {
+                    Some((host, port)) => {
+                        let mut proxy =
+                            ProxyConfig::new("http", host.clone(), *port, connector, tls);
+                        // The proxy's pool cannot be configured to evict stale
+                        // connections, so connections are not reused.
+                        proxy.set_pool_config(None);
+                        Client::with_proxy_config(proxy)
+                    }
+                    None => {
+                        let https = HttpsConnector::with_connector(tls, connector);
+                        let mut pool = Pool::with_connector(Default::default(), https);
+                        pool.set_idle_timeout(Some(POOL_IDLE_TIMEOUT));
+                        pool.set_stale_check(move |mut check| {
+                            if closed_by_peer(check.stream(), read_timeout) {
+                                check.stale()
+                            } else {
+                                check.fresh()
+                            }
+                        });
+                        Client::with_connector(pool)
+                    }
+                };
+                client.set_read_timeout(Some(read_timeout));
+                client.set_write_timeout(Some(self.timeout));
+                Arc::new(client)
+            })
+            .clone()
+    }
+
+    /// Send a form-encoded POST request to `uri`.
+    ///
+    /// Connection errors and `5xx` responses are retried as configured by
+    /// [`with_retries`](HyperSyncRustlsAdapter::with_retries). If
+    /// `retry_after_send` is false, only errors before the request was sent
+    /// are retried.
+    fn post(
+        &self,
+        uri: &str,
+        req: TokenRequestBody,
+        retry_after_send: bool,
+    ) -> Result<Response, Error> {
+        let accept = self.accept.as_deref().unwrap_or("application/json");
+        let req = http_client_adapter::form_post(uri, accept, req);
+        let response = self.request(&req, retry_after_send)?;
+        adapter_util::check_rate_limit(response.status.to_u16(), retry_after(&response))?;
+        Ok(response)
+    }
+
+    /// Send `req`, retrying connection errors, `5xx` responses, and `429`
+    /// responses with a `Retry-After` header as configured by
+    /// [`with_retries`](HyperSyncRustlsAdapter::with_retries) or
+    /// [`with_retry_policy`](HyperSyncRustlsAdapter::with_retry_policy).
+    ///
+    /// Errors which may have happened after the request was sent are only
+    /// passed to a retry policy, and responses are only retried, if
+    /// `retry_after_send` is true.
+    fn request(&self, req: &HttpRequest, retry_after_send: bool) -> Result<Response, Error> {
+        let uri = &req.uri;
+        let deadline = Instant::now() + self.timeout;
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let result = self.request_once(req);
+            let sent = match &result {
+                Ok(_) => true,
+                Err(e) => !never_sent(e),
+            };
+            if sent && !retry_after_send {
+                return result;
+            }
+
+            let wait = match (&self.retry_policy, &result) {
+                (Some(policy), Ok(response)) => {
+                    retryable_response_error(response).and_then(|e| (policy.0)(attempt, &e))
+                }
+                (Some(policy), Err(e)) => (policy.0)(attempt, e),
+                (None, _) if attempt >= self.max_attempts => None,
+                (None, Ok(response)) if response.status.is_server_error() => Some(delay),
+                (None, Ok(response)) => {
+                    match retry_after(response).and_then(adapter_util::parse_retry_after) {
+                        Some(retry_after)
+                            if response.status.to_u16() == 429
+                                && retry_after <= MAX_RETRY_DELAY =>
+                        {
+                            Some(delay.max(retry_after))
+                        }
+                        _ => None,
+                    }
+                }
+                (None, Err(_)) if sent => None,
+                (None, Err(_)) => Some(delay),
+            };
+            let wait = match wait {
+                Some(wait) if Instant::now() + wait < deadline => wait,
+                Some(_) => {
+                    log::warn!("Request to {} failed; not retrying after the timeout", uri);
+                    return result;
+                }
+                None => return result,
+            };
+
+            log::warn!("Request to {} failed; retrying in {:?}", uri, wait);
+            thread::sleep(wait);
+            if self.retry_policy.is_none() {
+                delay = delay
+                    .checked_mul(2)
+                    .unwrap_or(MAX_RETRY_DELAY)
+                    .min(MAX_RETRY_DELAY)
+                    .max(self.retry_delay);
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Returns the headers sent with every request: the `User-Agent` and any
+    /// added with [`with_header`](HyperSyncRustlsAdapter::with_header).
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        headers.set(UserAgent(self.user_agent.clone()));
+        for (name, value) in &self.headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+        headers
+    }
+
+    fn request_once(&self, req: &HttpRequest) -> Result<Response, Error> {
+        let client = self.client();
+
+        let method = req
+            .method
+            .as_str()
+            .parse()
+            .map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
+        let mut headers = self.headers();
+        for (name, value) in &req.headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+        let mut request = client.request(method, &req.uri).headers(headers);
+        if let Some(body) = &req.body {
+            request = request.body(body.as_str());
+        }
+
+        // Only the host is logged: the body and headers contain secrets.
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            method = req.method.as_str(),
+            host = %adapter_util::log_host(&req.uri),
+            "sending request"
+        );
+
+        let response = request.send();
+
+        #[cfg(feature = "tracing")]
+        match &response {
+            Ok(response) => tracing::debug!(status = response.status.to_u16(), "received response"),
+            Err(e) => tracing::warn!(error = %e, "request failed"),
+        }
+
+        response.map_err(|e| {
+            let network_kind = network_kind(&e);
+            let error = match e {
+                hyper::Error::Io(ref io)
+                    if io.kind() == io::ErrorKind::WouldBlock
+                        || io.kind() == io::ErrorKind::TimedOut =>
+                {
+                    Error::new_from(ErrorKind::Timeout, e)
+                }
+                e => Error::new_from(ErrorKind::ExchangeFailure, e),
+            };
+            error.with_network_kind(network_kind)
+        })
+    }
+}
+
+/// Returns the error passed to a retry policy for `response`, if it is a
+/// `5xx` response or a `429` response with a `Retry-After` header.
+fn retryable_response_error(response: &Response) -> Option<Error> {
+    let status = response.status.to_u16();
+    if response.status.is_server_error() {
+        return Some(Error::new(ErrorKind::ExchangeError {
+            status,
+            error: None,
+            description: None,
+            uri: None,
+        }));
+    }
+    match retry_after(response).and_then(adapter_util::parse_retry_after) {
+        Some(retry_after) if status == 429 => {
+            Some(Error::new(ErrorKind::RateLimited { retry_after }))
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if `error` happened before the request could be sent: the
+/// server's name could not be resolved or the connection was refused.
+fn never_sent(error: &Error) -> bool {
+    matches!(
+        error.network_kind(),
+        Some(NetworkErrorKind::Dns) | Some(NetworkErrorKind::Connect)
+    )
+}
+
+/// Redeeming these credentials more than once can fail, or cause the service
+/// provider to revoke the tokens already issued for them.
+fn redeems_single_use_credential(token: &TokenRequest) -> bool {
+    match token {
+        TokenRequest::AuthorizationCode { .. }
+        | TokenRequest::RefreshToken(_)
+        | TokenRequest::DeviceCode(_) => true,
+        TokenRequest::ClientCredentials { .. } => false,
+        #[cfg(feature = "deprecated_password_grant")]
+        TokenRequest::Password { .. } => false,
+    }
+}
+
+/// Returns the value of the `Retry-After` header of `response`, if any.
+fn retry_after(response: &Response) -> Option<&str> {
+    header(response, "Retry-After")
+}
+
+/// Returns the value of the header called `name` in `response`, if any.
+fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    let values = response.headers.get_raw(name)?;
+    values
+        .first()
+        .and_then(|value| std::str::from_utf8(value).ok())
+}
+
+impl HttpSend for HyperSyncRustlsAdapter {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+        let mut response = self.request(&request, request.method == Method::Get)?;
+        let mut body = vec![];
+        response
+            .read_to_end(&mut body)
+            .map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
+
+        let headers = response
+            .headers
+            .iter()
+            .map(|header| (header.name().to_string(), header.value_string()))
+            .collect();
+
+        Ok(HttpResponse {
+            status: response.status.to_u16(),
+            headers,
+            body,
+        })
+    }
+}
 
 impl Adapter for HyperSyncRustlsAdapter {
     fn authorization_uri(
@@ -28,26 +955,16 @@ impl Adapter for HyperSyncRustlsAdapter {
         config: &OAuthConfig,
         state: &str,
         scopes: &[&str],
+        extra_params: &[(&str, &str)],
     ) -> Result<Absolute<'static>, Error> {
-        let auth_uri = config.provider().auth_uri();
-
-        let mut url = Url::parse(&auth_uri)
-            .map_err(|e| Error::new_from(ErrorKind::InvalidUri(auth_uri.to_string()), e))?;
-
-        url.query_pairs_mut()
-            .append_pair("response_type", "code")
-            .append_pair("client_id", config.client_id())
-            .append_pair("redirect_uri", config.redirect_uri())
-            .append_pair("state", state);
-
-        if !scopes.is_empty() {
-            url.query_pairs_mut()
-                .append_pair("scope", &scopes.join(" "));
-        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            host = %adapter_util::log_host(&config.provider().auth_uri()),
+            scopes = %scopes.join(" "),
+            "generating authorization URI"
+        );
 
-        Ok(Absolute::parse(url.as_ref())
-            .map_err(|_| Error::new(ErrorKind::InvalidUri(url.to_string())))?
-            .into_owned())
+        request_util::authorization_uri(config, state, scopes, extra_params)
     }
 
     fn exchange_code(
@@ -55,44 +972,184 @@ impl Adapter for HyperSyncRustlsAdapter {
         config: &OAuthConfig,
         token: TokenRequest,
     ) -> Result<TokenResponse, Error> {
-        let https = HttpsConnector::new(hyper_sync_rustls::TlsClient::new());
-        let client = Client::with_connector(https);
-
-        let mut ser = UrlSerializer::new(String::new());
-        match token {
-            TokenRequest::AuthorizationCode(code) => {
-                ser.append_pair("grant_type", "authorization_code");
-                ser.append_pair("code", &code);
-                ser.append_pair("redirect_uri", config.redirect_uri());
-            }
-            TokenRequest::RefreshToken(token) => {
-                ser.append_pair("grant_type", "refresh_token");
-                ser.append_pair("refresh_token", &token);
-            }
-        }
-        ser.append_pair("client_id", config.client_id());
-        ser.append_pair("client_secret", config.client_secret());
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "token_exchange",
+            grant_type = config.grant_type(request_util::grant_type(&token)),
+            client_id = config.client_id(),
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
 
-        let req_str = ser.finish();
+        let retry_after_send = !redeems_single_use_credential(&token);
+        let req = adapter_util::token_request_body(config, token)?;
+        let uri = config.provider().token_uri();
+        let response = self.post(uri.as_ref(), req, retry_after_send)?;
 
-        let request = client
-            .post(config.provider().token_uri().as_ref())
+        let content_type = header(&response, "Content-Type").map(String::from);
+        let date = header(&response, "Date").map(String::from);
+        adapter_util::token_response(
+            response.status.to_u16(),
+            content_type.as_deref(),
+            date.as_deref(),
+            response,
+        )
+    }
+
+    fn discover(&self, issuer: &str) -> Result<DiscoveredProvider, Error> {
+        let response = self
+            .client()
+            .get(&adapter_util::discovery_uri(issuer))
+            .headers(self.headers())
             .header(Accept::json())
-            .header(ContentType::form_url_encoded())
-            .body(&req_str);
+            .send()
+            .map_err(|e| {
+                let network_kind = network_kind(&e);
+                Error::new_from(ErrorKind::DiscoveryFailure, e).with_network_kind(network_kind)
+            })?;
 
-        let response = request
+        adapter_util::discovery_response(issuer, response.status.to_u16(), response)
+    }
+
+    fn fetch_jwks(&self, jwks_uri: &str) -> Result<Value, Error> {
+        let response = self
+            .client()
+            .get(jwks_uri)
+            .headers(self.headers())
+            .header(Accept::json())
             .send()
-            .map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
+            .map_err(|e| {
+                let network_kind = network_kind(&e);
+                Error::new_from(ErrorKind::DiscoveryFailure, e).with_network_kind(network_kind)
+            })?;
 
-        if !response.status.is_success() {
-            return Err(Error::new(ErrorKind::ExchangeError(
-                response.status.to_u16(),
-            )));
-        }
+        adapter_util::jwks_response(response.status.to_u16(), response)
+    }
 
-        let data: serde_json::Value = serde_json::from_reader(response.take(2 * 1024 * 1024))
-            .map_err(|e| Error::new_from(ErrorKind::ExchangeFailure, e))?;
-        Ok(data.try_into()?)
+    fn revoke(
+        &self,
+        config: &OAuthConfig,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), Error> {
+        let (uri, req) = adapter_util::revocation_request_body(config, token, token_type_hint)?;
+        let response = self.post(&uri, req, true)?;
+
+        adapter_util::revocation_response(response.status.to_u16(), response)
+    }
+
+    fn introspect(&self, config: &OAuthConfig, token: &str) -> Result<Introspection, Error> {
+        let (uri, req) = adapter_util::introspection_request_body(config, token)?;
+        let response = self.post(&uri, req, true)?;
+
+        adapter_util::introspection_response(response.status.to_u16(), response)
+    }
+
+    fn device_authorization(
+        &self,
+        config: &OAuthConfig,
+        scopes: &[&str],
+    ) -> Result<DeviceAuthorization, Error> {
+        let (uri, req) = adapter_util::device_authorization_request_body(config, scopes)?;
+        let response = self.post(&uri, req, true)?;
+
+        adapter_util::device_authorization_response(response.status.to_u16(), response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A P-256 certificate authority, and two certificates it issued for
+    // example.com with different keys.
+    const CA: &str = concat!(
+        "MIIBgjCCASegAwIBAgIUP6VcxCwbd5jrSXrV9hvJPYVkz+swCgYIKoZIzj0EAwIwDTELMAkGA1UE",
+        "AwwCY2EwIBcNMjYxMDE2MDIwOTE1WhgPMjEyNjA5MjIwMjA5MTVaMA0xCzAJBgNVBAMMAmNhMFkw",
+        "EwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAERgMP4GWO6pHAI2jQwN5h76gtpbMieCRLZOhvddJifujN",
+        "O/VPU3eq/EA/T/frelS6qANvsjtBOl/sffzSasyqt6NjMGEwHQYDVR0OBBYEFFCHaQry2aXwQy1D",
+        "TrkBAEn4dXO6MB8GA1UdIwQYMBaAFFCHaQry2aXwQy1DTrkBAEn4dXO6MA8GA1UdEwEB/wQFMAMB",
+        "Af8wDgYDVR0PAQH/BAQDAgIEMAoGCCqGSM49BAMCA0kAMEYCIQDnGFGUOeJEx4i2uRoc1hTCIoxS",
+        "OjSoqTev6DjEVKcsSwIhAIqQ5LShS0qDiNJiyirgwpI/Y+KK9fdzVmlOr5kzmbTn",
+    );
+    const PINNED: &str = concat!(
+        "MIIBmzCCAUCgAwIBAgIUePP+OU5H7cSOldVirOmTbqjQUq4wCgYIKoZIzj0EAwIwDTELMAkGA1UE",
+        "AwwCY2EwIBcNMjYxMDE2MDIwOTE1WhgPMjEyNjA5MjIwMjA5MTVaMA8xDTALBgNVBAMMBHJlYWww",
+        "WTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAARrGbOe6QRmYI/f4d6DP4h/Z2Y/quUAsyBW++7AhmQ+",
+        "7LNwRCpniRJ7lCNeLGmQisczu+vxXcSsdHhGqgdOx+mCo3oweDAWBgNVHREEDzANggtleGFtcGxl",
+        "LmNvbTATBgNVHSUEDDAKBggrBgEFBQcDATAJBgNVHRMEAjAAMB0GA1UdDgQWBBTxNohMeZHgxjUP",
+        "AA/cTZ0BZF8OzTAfBgNVHSMEGDAWgBRQh2kK8tml8EMtQ065AQBJ+HVzujAKBggqhkjOPQQDAgNJ",
+        "ADBGAiEAlaiDS37Bh19X3w7GN3omgx3vdLXWcKGEIV1X9rsKcY0CIQDG2NPGGT31eZsv600yB6kG",
+        "NBW5k8pqtBGw3uCzAHOa/Q==",
+    );
+    const PIN: &str = "z9rth4L0cEVtqrujms4lxaygAxHMy41pW5h59g7CO5s=";
+    const MIS_ISSUED: &str = concat!(
+        "MIIBnjCCAUSgAwIBAgIUePP+OU5H7cSOldVirOmTbqjQUq8wCgYIKoZIzj0EAwIwDTELMAkGA1UE",
+        "AwwCY2EwIBcNMjYxMDE2MDIwOTE2WhgPMjEyNjA5MjIwMjA5MTZaMBMxETAPBgNVBAMMCGF0dGFj",
+        "a2VyMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEbEJVSxoWmVr4LT8/lwyN7bw2tYXI2bhNNoCH",
+        "hOxKiKFR+skneIA8h2g0YPPyp8tkJWq4vgs1mndiAf5TG8hA9KN6MHgwFgYDVR0RBA8wDYILZXhh",
+        "bXBsZS5jb20wEwYDVR0lBAwwCgYIKwYBBQUHAwEwCQYDVR0TBAIwADAdBgNVHQ4EFgQUl9rWUn9W",
+        "A+OqwfFLyO2riyAJd1UwHwYDVR0jBBgwFoAUUIdpCvLZpfBDLUNOuQEASfh1c7owCgYIKoZIzj0E",
+        "AwIDSAAwRQIgDXWQxXZO+u6PvXXSQu2ZnRyhq7TcFT5G1JFXZO1nducCIQDmxb+Wwy7RyV7PAfZM",
+        "iIJPqjGlh8RDG6CL0iz0ajP9kw==",
+    );
+
+    #[test]
+    fn connection_closed_by_peer_is_stale() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let timeout = Duration::from_secs(5);
+
+        let mut open = HttpStream(TcpStream::connect(addr).unwrap());
+        let (_server, _) = listener.accept().unwrap();
+        assert!(!closed_by_peer(&mut open, timeout));
+        assert_eq!(open.0.read_timeout().unwrap(), Some(timeout));
+
+        let mut closed = HttpStream(TcpStream::connect(addr).unwrap());
+        drop(listener.accept().unwrap());
+        thread::sleep(Duration::from_millis(50));
+        assert!(closed_by_peer(&mut closed, timeout));
+    }
+
+    fn cert(der: &str) -> rustls::Certificate {
+        rustls::Certificate(base64::decode(der).unwrap())
+    }
+
+    fn verify(presented_certs: &[rustls::Certificate]) -> Result<(), rustls::TLSError> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(&cert(CA)).unwrap();
+        let verifier = PinningVerifier {
+            pinned_keys: vec![base64::decode(PIN).unwrap()],
+        };
+        let dns_name = webpki::DNSNameRef::try_from_ascii_str("example.com").unwrap();
+
+        rustls::ServerCertVerifier::verify_server_cert(
+            &verifier,
+            &roots,
+            presented_certs,
+            dns_name,
+            &[],
+        )
+        .map(|_| ())
+    }
+
+    #[test]
+    fn pinned_end_entity_is_accepted() {
+        assert!(verify(&[cert(PINNED)]).is_ok());
+    }
+
+    #[test]
+    fn unpinned_end_entity_is_rejected() {
+        assert!(verify(&[cert(MIS_ISSUED)]).is_err());
+    }
+
+    #[test]
+    fn appended_pinned_certificate_is_rejected() {
+        // The mis-issued certificate is valid on its own, so appending the
+        // pinned certificate must not make the chain acceptable.
+        assert!(verify(&[cert(MIS_ISSUED), cert(PINNED)]).is_err());
+        assert!(verify(&[cert(MIS_ISSUED), cert(PINNED), cert(CA)]).is_err());
     }
 }
@@ -0,0 +1,239 @@
+//! Verification of OpenID Connect ID Tokens (OpenID Connect Core 1.0 §3.1.3.7).
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ring::{constant_time, signature};
+use serde_json::Value;
+
+use crate::{Error, ErrorKind, JwsAlgorithm, OAuthConfig};
+
+/// The minimum time between fetches of the JSON Web Key Set.
+const JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The service provider's JSON Web Key Set, and when it was last fetched.
+#[derive(Default)]
+pub(crate) struct JwksCache {
+    pub(crate) keys: Option<Value>,
+    fetched_at: Option<Instant>,
+}
+
+impl JwksCache {
+    /// Returns true, and records the time, if the key set was not fetched in
+    /// the last [`JWKS_REFETCH_INTERVAL`]. This stops tokens with unknown
+    /// keys from making every request fetch the key set.
+    pub(crate) fn start_fetch(&mut self) -> bool {
+        if self
+            .fetched_at
+            .is_some_and(|at| at.elapsed() < JWKS_REFETCH_INTERVAL)
+        {
+            return false;
+        }
+        self.fetched_at = Some(Instant::now());
+        true
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new_from(ErrorKind::InvalidIdToken, message)
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, Error> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))
+}
+
+/// A decoded, but not yet verified, ID Token.
+pub(crate) struct IdToken<'a> {
+    alg: JwsAlgorithm,
+    kid: Option<String>,
+    message: &'a str,
+    signature: Vec<u8>,
+    claims: Value,
+}
+
+impl<'a> IdToken<'a> {
+    /// Split and decode the JWS Compact Serialization of `id_token`.
+    pub(crate) fn parse(id_token: &'a str) -> Result<IdToken<'a>, Error> {
+        let (message, signature) = match id_token.rfind('.') {
+            Some(i) => (&id_token[..i], &id_token[i + 1..]),
+            None => return Err(invalid(String::from("id_token is not a JWT"))),
+        };
+        let (header, payload) = match message.split('.').collect::<Vec<_>>().as_slice() {
+            [header, payload] => (*header, *payload),
+            _ => return Err(invalid(String::from("id_token is not a JWT"))),
+        };
+
+        let header: Value = serde_json::from_slice(&decode(header)?)
+            .map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))?;
+        let claims: Value = serde_json::from_slice(&decode(payload)?)
+            .map_err(|e| Error::new_from(ErrorKind::InvalidIdToken, e))?;
+
+        // Only asymmetric algorithms are accepted; in particular, "none" and
+        // the HMAC algorithms are rejected.
+        let alg = match header.get("alg").and_then(Value::as_str) {
+            Some("RS256") => JwsAlgorithm::RS256,
+            Some("ES256") => JwsAlgorithm::ES256,
+            Some(alg) => return Err(invalid(format!("unsupported id_token alg '{}'", alg))),
+            None => return Err(invalid(String::from("id_token header has no alg"))),
+        };
+        let kid = header.get("kid").and_then(Value::as_str).map(String::from);
+
+        Ok(IdToken {
+            alg,
+            kid,
+            message,
+            signature: decode(signature)?,
+            claims,
+        })
+    }
+
+    /// The keys of `jwks` which could have signed this token.
+    fn candidate_keys<'j>(&self, jwks: &'j Value) -> Vec<&'j Value> {
+        let field = |key: &'j Value, name: &str| key.get(name).and_then(Value::as_str);
+        let (kty, crv) = match self.alg {
+            JwsAlgorithm::RS256 => ("RSA", None),
+            JwsAlgorithm::ES256 => ("EC", Some("P-256")),
+        };
+
+        jwks.get("keys")
+            .and_then(Value::as_array)
+            .map(|keys| {
+                keys.iter()
+                    .filter(|key| field(key, "kty") == Some(kty))
+                    .filter(|key| crv.is_none() || field(key, "crv") == crv)
+                    .filter(|key| field(key, "use").is_none_or(|u| u == "sig"))
+                    .filter(|key| field(key, "alg").is_none_or(|a| a == self.alg.as_str()))
+                    .filter(|key| match &self.kid {
+                        Some(kid) => field(key, "kid") == Some(kid),
+                        None => true,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns true if `jwks` contains a key which could have signed this
+    /// token.
+    pub(crate) fn has_key(&self, jwks: &Value) -> bool {
+        !self.candidate_keys(jwks).is_empty()
+    }
+
+    /// Verify the signature of this token with the keys in `jwks`.
+    pub(crate) fn verify_signature(&self, jwks: &Value) -> Result<(), Error> {
+        let keys = self.candidate_keys(jwks);
+        if keys.is_empty() {
+            return Err(invalid(String::from(
+                "no key in the JWKS matches the id_token",
+            )));
+        }
+
+        for key in keys {
+            if self.verify_with(key).is_ok() {
+                return Ok(());
+            }
+        }
+        Err(invalid(String::from("id_token signature is invalid")))
+    }
+
+    fn verify_with(&self, key: &Value) -> Result<(), Error> {
+        let param = |name: &str| {
+            key.get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid(format!("JWK has no '{}' parameter", name)))
+                .and_then(decode)
+        };
+        let message = untrusted::Input::from(self.message.as_bytes());
+        let sig = untrusted::Input::from(&self.signature);
+
+        let result = match self.alg {
+            JwsAlgorithm::RS256 => {
+                let (n, e) = (param("n")?, param("e")?);
+                signature::primitive::verify_rsa(
+                    &signature::RSA_PKCS1_2048_8192_SHA256,
+                    (untrusted::Input::from(&n), untrusted::Input::from(&e)),
+                    message,
+                    sig,
+                )
+            }
+            JwsAlgorithm::ES256 => {
+                let mut point = vec![0x04];
+                point.extend(param("x")?);
+                point.extend(param("y")?);
+                signature::verify(
+                    &signature::ECDSA_P256_SHA256_FIXED,
+                    untrusted::Input::from(&point),
+                    message,
+                    sig,
+                )
+            }
+        };
+        result.map_err(|_| invalid(String::from("id_token signature is invalid")))
+    }
+
+    /// Validate the `iss`, `aud`, `azp`, `exp`, and `nonce` claims, and
+    /// return all of the claims.
+    pub(crate) fn validate_claims(
+        self,
+        config: &OAuthConfig,
+        expected_nonce: Option<&str>,
+    ) -> Result<Value, Error> {
+        let claims = self.claims;
+        let claim = |name: &str| claims.get(name).and_then(Value::as_str);
+
+        let issuer = config.issuer().ok_or_else(|| {
+            invalid(String::from(
+                "no issuer is configured to verify the id_token against",
+            ))
+        })?;
+        if claim("iss") != Some(&*issuer) {
+            return Err(invalid(format!(
+                "id_token issuer {:?} does not match '{}'",
+                claim("iss"),
+                issuer
+            )));
+        }
+
+        let client_id = config.client_id();
+        let audience_ok = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == client_id,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(client_id)),
+            _ => false,
+        };
+        if !audience_ok {
+            return Err(invalid(String::from(
+                "id_token audience does not include the client ID",
+            )));
+        }
+        if let Some(azp) = claim("azp") {
+            if azp != client_id {
+                return Err(invalid(format!(
+                    "id_token authorized party '{}' is not the client ID",
+                    azp
+                )));
+            }
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        match claims.get("exp").and_then(Value::as_u64) {
+            Some(exp) if exp > now => {}
+            Some(_) => return Err(invalid(String::from("id_token has expired"))),
+            None => return Err(invalid(String::from("id_token has no exp claim"))),
+        }
+
+        if let Some(expected) = expected_nonce {
+            let nonce = claim("nonce").unwrap_or("");
+            if constant_time::verify_slices_are_equal(nonce.as_bytes(), expected.as_bytes())
+                .is_err()
+            {
+                return Err(invalid(String::from(
+                    "id_token nonce does not match the expected nonce",
+                )));
+            }
+        }
+
+        Ok(claims)
+    }
+}
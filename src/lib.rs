@@ -19,10 +19,20 @@
 //! * Support for custom providers
 //! * Support for custom adapters
 //! * Refreshing tokens
+//! * Revoking tokens (RFC 7009)
+//! * Token introspection (RFC 7662)
+//! * Device Authorization Grant (RFC 8628)
+//! * Client Credentials Grant (RFC 6749, §4.4)
+//! * PKCE (RFC 7636)
+//! * OpenID Connect discovery
 //!
 //! ## Not-yet-planned Features
 //!
-//! * Grant types other than Authorization Code.
+//! * The Implicit Grant.
+//!
+//! The Resource Owner Password Credentials Grant (RFC 6749, §4.3) is only
+//! available with the `deprecated_password_grant` feature, for migrating
+//! legacy systems; it should not be used otherwise.
 //!
 //! ## Design
 //!
@@ -39,11 +49,33 @@
 //!
 //! ## Implementations
 //!
-//! `rocket_oauth2` currently provides only one [`Adapter`] itself:
+//! `rocket_oauth2` currently provides two [`Adapter`]s itself:
 //!
 //! * `hyper_sync_rustls`: Uses [`hyper-sync-rustls`](https://github.com/SergioBenitez/hyper-sync-rustls).
+//!   Enabled by default with the `hyper_sync_rustls_adapter` feature.
+//! * `reqwest`: Uses a blocking [`reqwest`](https://github.com/seanmonstar/reqwest)
+//!   client, which can be shared with the rest of the application. Enabled
+//!   with the `reqwest_adapter` feature.
+//!
+//! `hyper_sync_rustls` was chosen as the default because it is already a dependency of Rocket.
+//!
+//! To use another HTTP client, implement the small `HttpSend` trait for it
+//! and use it with the `HttpClientAdapter`, enabled with the
+//! `http_client_adapter` feature. This reuses the request building and
+//! response handling of the built-in adapters.
+//!
+//! For testing, the `mock_adapter` feature provides a `MockAdapter` which
+//! returns a preconfigured token without making any network requests.
+//!
+//! The `tracing` feature adds [`tracing`](https://docs.rs/tracing) spans and
+//! events to `HyperSyncRustlsAdapter`'s requests. Secrets such as the client
+//! secret, authorization code, and tokens are never recorded.
+//!
+//! The `openid` feature enables [`TokenResponse::decode_id_token`], which
+//! decodes the claims of an OpenID Connect ID Token without verifying it, and
+//! `OAuth2::verify_id_token`, which verifies an ID Token's signature with the
+//! provider's JSON Web Key Set and validates its claims.
 //!
-//! `hyper_sync_rustls` was chosen because it is already a dependency of Rocket.
 //! In general, custom `Adapter`s should only be needed to work around
 //! non-compliant service providers.
 //!
@@ -111,7 +143,7 @@
 //! # fn check_only() {
 //! rocket::ignite()
 //! .attach(OAuth2::fairing(
-//!     HyperSyncRustlsAdapter,
+//!     HyperSyncRustlsAdapter::new(),
 //!     github_callback,
 //!     "github",
 //!
@@ -150,18 +182,182 @@
 //! client_secret = "..."
 //! redirect_uri = "http://localhost:8000/auth/custom"
 //! ```
+//!
+//! ### Multiple providers
+//!
+//! Each provider is attached with its own fairing and configuration name.
+//! When the providers share a callback type, or the callbacks are wrapped in
+//! [`AnyCallback`], the provider can be chosen at runtime by its
+//! configuration name with the [`OAuth2Registry`] in managed state.
+//!
+//! ### PKCE
+//!
+//! Proof Key for Code Exchange ([RFC 7636](https://tools.ietf.org/html/rfc7636))
+//! can be enabled with the `pkce` key. `pkce = true` uses the `S256` challenge
+//! method; `pkce = "plain"` can be used for providers that do not support it.
+//!
+//! ```toml
+//! [global.oauth.custom]
+//! provider = { auth_uri = "https://example.com/oauth/authorize", token_uri = "https://example.com/oauth/token" }
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "http://localhost:8000/auth/custom"
+//! pkce = true
+//! ```
+//!
+//! Public clients, which cannot keep a secret, can omit `client_secret` and
+//! rely on PKCE instead. Only the `client_id` is then sent to the token
+//! endpoint.
+//!
+//! ### Additional parameters
+//!
+//! Some providers accept or require additional parameters in the
+//! authorization URI. These can be specified in the `extra_params` table, and
+//! are included in every authorization URI:
+//!
+//! ```toml
+//! [global.oauth.google]
+//! provider = "Google"
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "http://localhost:8000/auth/google"
+//! extra_params = { access_type = "offline", prompt = "consent" }
+//! ```
+//!
+//! Parameters for a single authorization request can be passed to
+//! [`OAuth2::get_redirect_extras`].
+//!
+//! The `audience` parameter required by service providers such as Auth0 can
+//! also be set with the `audience` key. Resource indicators (RFC 8707) can
+//! be set with the `resources` array, and are sent in both the authorization
+//! URI and token requests.
+//!
+//! Scopes which should be requested every time, such as `openid`, can be
+//! set with the `default_scopes` array. They are requested before the scopes
+//! given for each request, and each scope is only requested once.
+//!
+//! Scopes are separated by spaces in the authorization URI. For service
+//! providers which expect a different separator, set `scope_delimiter`:
+//!
+//! ```toml
+//! [global.oauth.facebook]
+//! provider = "Facebook"
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "http://localhost:8000/auth/facebook"
+//! scope_delimiter = ","
+//! ```
+//!
+//! The `scope` parameter is omitted if no scopes are requested, unless
+//! `include_empty_scope = true` is set.
+//!
+//! For service providers which expect different names for the standard
+//! parameters of token requests, the names can be changed with the
+//! `token_param_names` table:
+//!
+//! ```toml
+//! [global.oauth.custom]
+//! provider = { auth_uri = "https://example.com/oauth/authorize", token_uri = "https://example.com/oauth/token" }
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "http://localhost:8000/auth/custom"
+//! token_param_names = { client_id = "clientId", client_secret = "clientSecret" }
+//! ```
+//!
+//! Similarly, vendor-specific grant types can be sent in place of the
+//! standard ones with the `grant_types` table, for example
+//! `grant_types = { authorization_code = "urn:example:authorization_code" }`.
+//!
+//! ### State cookie
+//!
+//! The state cookie is sent with `SameSite=Lax` and `Secure` by default. These
+//! can be changed with `state_cookie_same_site` (`"Strict"`, `"Lax"`, or
+//! `"None"`) and `state_cookie_secure`, for example to develop locally over
+//! plain HTTP:
+//!
+//! ```toml
+//! [development.oauth.github]
+//! provider = "GitHub"
+//! client_id = "..."
+//! client_secret = "..."
+//! redirect_uri = "http://localhost:8000/auth/github"
+//! state_cookie_secure = false
+//! ```
+//!
+//! The cookie expires after 10 minutes, or `state_cookie_max_age` seconds if
+//! set, after which the user must start the authorization again.
+//!
+//! The cookie is named `rocket_oauth2_state` unless `state_cookie_name` is
+//! set. Each configuration used by the same application at the same time
+//! should have a different `state_cookie_name`.
+//!
+//! The cookie is a private cookie, encrypted with Rocket's secret key. For
+//! deployments without a stable secret key, `state_cookie_protection =
+//! "signed"` signs it with the base64-encoded `state_cookie_signing_key`
+//! instead, and `"plain"` leaves it unprotected for use with a server-side
+//! [`StateGenerator`].
+//!
+//! ### Redirect base
+//!
+//! Instead of a `redirect_uri`, a `redirect_base` with the scheme, host, and
+//! any base path of the application can be given. The redirect URI is then
+//! built from it and the path of the redirect handler mounted by the fairing:
+//!
+//! ```toml
+//! [global.oauth.github]
+//! provider = "GitHub"
+//! client_id = "..."
+//! client_secret = "..."
+//! # With the fairing's "/auth/github", the redirect URI is
+//! # "https://example.com/app/auth/github"
+//! redirect_base = "https://example.com/app"
+//! ```
+//!
+//! ### Form post responses
+//!
+//! Setting `response_mode = "form_post"` asks the service provider to return
+//! the authorization response in the body of a `POST` request to the redirect
+//! URI, rather than in its query string. The redirect handler accepts both.
+//!
+//! ### OpenID Connect discovery
+//!
+//! The endpoints of an OpenID Connect provider can be discovered from its
+//! issuer URL instead of being configured explicitly, using
+//! [`OAuthConfig::from_issuer`]. The resulting configuration can be used with
+//! [`OAuth2::custom`].
 
 #![warn(future_incompatible, nonstandard_style, missing_docs)]
 
+#[cfg(any(feature = "http_client_adapter", feature = "reqwest_adapter"))]
+mod adapter_util;
 mod config;
 mod core;
+mod device;
 mod error;
+#[cfg(feature = "openid")]
+mod id_token;
 mod provider;
+#[cfg(any(
+    feature = "http_client_adapter",
+    feature = "reqwest_adapter",
+    feature = "mock_adapter"
+))]
+mod request_util;
 
 pub use self::config::*;
 pub use self::core::*;
+pub use self::device::*;
 pub use self::error::*;
 pub use self::provider::*;
 
+#[cfg(feature = "http_client_adapter")]
+pub mod http_client_adapter;
+
 #[cfg(feature = "hyper_sync_rustls_adapter")]
 pub mod hyper_sync_rustls_adapter;
+
+#[cfg(feature = "reqwest_adapter")]
+pub mod reqwest_adapter;
+
+#[cfg(feature = "mock_adapter")]
+pub mod mock_adapter;
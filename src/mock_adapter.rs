@@ -0,0 +1,174 @@
+//! [Adapter] that returns a preconfigured token without making any network
+//! requests, for use in tests.
+
+use std::sync::{Arc, Mutex};
+
+use rocket::http::uri::Absolute;
+
+use super::{
+    Adapter, Error, ErrorKind, Introspection, OAuthConfig, TokenRequest, TokenResponse,
+    TokenTypeHint,
+};
+use crate::request_util;
+
+/// `Adapter` implementation that returns a preconfigured [`TokenResponse`]
+/// from every token exchange, and records the [`TokenRequest`]s it receives
+/// as [`MockRequest`]s.
+///
+/// Clones of a `MockAdapter` share the same record of requests, so a clone
+/// can be kept by a test after the original is given to
+/// [`OAuth2`](crate::OAuth2).
+///
+/// ```rust
+/// # extern crate rocket_oauth2;
+/// # extern crate serde_json;
+/// use std::convert::TryFrom;
+///
+/// use rocket_oauth2::mock_adapter::{MockAdapter, MockRequest};
+/// use rocket_oauth2::{Adapter, OAuthConfig, StaticProvider, TokenRequest, TokenResponse};
+///
+/// let token = TokenResponse::try_from(serde_json::json!({
+///     "access_token": "mock-access-token",
+///     "token_type": "bearer",
+/// })).unwrap();
+/// let adapter = MockAdapter::new(token);
+///
+/// let config = OAuthConfig::new(
+///     StaticProvider::GitHub,
+///     "client id".to_string(),
+///     "client secret".to_string(),
+///     "http://localhost:8000/auth/github".to_string(),
+/// );
+/// let request = TokenRequest::AuthorizationCode {
+///     code: "mock-code".to_string(),
+///     code_verifier: None,
+/// };
+/// let response = adapter.exchange_code(&config, request.clone()).unwrap();
+///
+/// assert_eq!(response.access_token(), "mock-access-token");
+/// assert_eq!(adapter.requests(), vec![MockRequest {
+///     request,
+///     redirect_uri: "http://localhost:8000/auth/github".to_string(),
+///     grant_type: "authorization_code".to_string(),
+/// }]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockAdapter {
+    token: TokenResponse,
+    authorization_uri: Option<Absolute<'static>>,
+    introspection: Option<Introspection>,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+/// A token request received by a [`MockAdapter`], with the parameters a real
+/// adapter would have sent for it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MockRequest {
+    /// The token request.
+    pub request: TokenRequest,
+    /// The `redirect_uri` from the configuration the request was made with.
+    /// It is only sent for [`TokenRequest::AuthorizationCode`].
+    pub redirect_uri: String,
+    /// The `grant_type` that would have been sent, including any override
+    /// from [`OAuthConfig::with_grant_type`].
+    pub grant_type: String,
+}
+
+impl MockAdapter {
+    /// Create a new `MockAdapter` that will return `token` from every token
+    /// exchange.
+    ///
+    /// Authorization URIs are generated from the configuration in the same
+    /// way as the other built-in adapters.
+    pub fn new(token: TokenResponse) -> MockAdapter {
+        MockAdapter {
+            token,
+            authorization_uri: None,
+            introspection: None,
+            requests: Default::default(),
+        }
+    }
+
+    /// Sets a fixed URI to be returned as the authorization URI, regardless
+    /// of configuration, state, or scopes.
+    pub fn with_authorization_uri(mut self, uri: Absolute<'static>) -> MockAdapter {
+        self.authorization_uri = Some(uri);
+        self
+    }
+
+    /// Sets the [`Introspection`] to be returned from every introspection
+    /// request. Without one, introspection requests fail with
+    /// [`ErrorKind::Unsupported`].
+    pub fn with_introspection(mut self, introspection: Introspection) -> MockAdapter {
+        self.introspection = Some(introspection);
+        self
+    }
+
+    /// Returns the token requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Adapter for MockAdapter {
+    fn authorization_uri(
+        &self,
+        config: &OAuthConfig,
+        state: &str,
+        scopes: &[&str],
+        extra_params: &[(&str, &str)],
+    ) -> Result<Absolute<'static>, Error> {
+        match &self.authorization_uri {
+            Some(uri) => Ok(uri.clone()),
+            None => request_util::authorization_uri(config, state, scopes, extra_params),
+        }
+    }
+
+    fn exchange_code(
+        &self,
+        config: &OAuthConfig,
+        token: TokenRequest,
+    ) -> Result<TokenResponse, Error> {
+        let request = MockRequest {
+            redirect_uri: config.redirect_uri().to_string(),
+            grant_type: config
+                .grant_type(request_util::grant_type(&token))
+                .to_string(),
+            request: token,
+        };
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(request);
+        Ok(self.token.clone())
+    }
+
+    fn revoke(
+        &self,
+        config: &OAuthConfig,
+        _token: &str,
+        _token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), Error> {
+        // Fail in the same way as the other adapters when there is no
+        // revocation endpoint.
+        match config.revocation_uri() {
+            Some(_) => Ok(()),
+            None => Err(Error::new_from(
+                ErrorKind::Unsupported,
+                String::from("no revocation_uri is configured"),
+            )),
+        }
+    }
+
+    fn introspect(&self, _config: &OAuthConfig, _token: &str) -> Result<Introspection, Error> {
+        self.introspection.clone().ok_or_else(|| {
+            Error::new_from(
+                ErrorKind::Unsupported,
+                String::from("no introspection response is configured"),
+            )
+        })
+    }
+}
@@ -1,4 +1,9 @@
 use std::borrow::Cow;
+use std::convert::TryFrom;
+
+use serde_json::Value;
+
+use crate::{Error, ErrorKind};
 
 /// A `Provider` can retrieve authorization and token exchange URIs specific to
 /// an OAuth service provider.
@@ -11,6 +16,42 @@ pub trait Provider: Send + Sync + 'static {
     fn auth_uri(&self) -> Cow<'_, str>;
     /// Returns the token exchange URI associated with the service provider.
     fn token_uri(&self) -> Cow<'_, str>;
+
+    /// Returns the OpenID Connect issuer identifier of the service provider,
+    /// if it has one.
+    fn issuer(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Returns the OpenID Connect UserInfo endpoint URI associated with the
+    /// service provider, if it has one.
+    fn userinfo_uri(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Returns the URI of the service provider's JSON Web Key Set, if it has
+    /// one.
+    fn jwks_uri(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Returns the token revocation URI (RFC 7009) associated with the
+    /// service provider, if it has one.
+    fn revocation_uri(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Returns the token introspection URI (RFC 7662) associated with the
+    /// service provider, if it has one.
+    fn introspection_uri(&self) -> Option<Cow<'_, str>> {
+        None
+    }
+
+    /// Returns the device authorization URI (RFC 8628) associated with the
+    /// service provider, if it has one.
+    fn device_authorization_uri(&self) -> Option<Cow<'_, str>> {
+        None
+    }
 }
 
 /// A `StaticProvider` contains authorization and token exchange URIs specific
@@ -27,6 +68,36 @@ pub struct StaticProvider {
     pub token_uri: Cow<'static, str>,
 }
 
+impl StaticProvider {
+    /// Create a `StaticProvider` with the given authorization and token
+    /// exchange URIs, for a service provider which is not built in.
+    ///
+    /// ```rust
+    /// use rocket_oauth2::{OAuthConfig, StaticProvider};
+    ///
+    /// let provider = StaticProvider::new(
+    ///     "https://example.com/oauth/authorize",
+    ///     "https://example.com/oauth/token",
+    /// );
+    /// let config = OAuthConfig::new(
+    ///     provider,
+    ///     "client id".to_string(),
+    ///     "client secret".to_string(),
+    ///     "http://localhost:8000/auth/example".to_string(),
+    /// );
+    /// ```
+    pub fn new<A, T>(auth_uri: A, token_uri: T) -> StaticProvider
+    where
+        A: Into<Cow<'static, str>>,
+        T: Into<Cow<'static, str>>,
+    {
+        StaticProvider {
+            auth_uri: auth_uri.into(),
+            token_uri: token_uri.into(),
+        }
+    }
+}
+
 impl Provider for StaticProvider {
     fn auth_uri(&self) -> Cow<'_, str> {
         Cow::Borrowed(&*self.auth_uri)
@@ -37,6 +108,108 @@ impl Provider for StaticProvider {
     }
 }
 
+/// A `DiscoveredProvider` contains the endpoints of an OpenID Connect
+/// provider, as described by its discovery document (OpenID Connect
+/// Discovery 1.0 §3).
+///
+/// A `DiscoveredProvider` is usually obtained from
+/// [`OAuthConfig::from_issuer`](crate::OAuthConfig::from_issuer), which
+/// fetches the discovery document once and keeps the result for the lifetime
+/// of the configuration.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DiscoveredProvider {
+    data: Value,
+}
+
+impl TryFrom<Value> for DiscoveredProvider {
+    type Error = Error;
+
+    /// Construct a DiscoveredProvider from a discovery document.
+    ///
+    /// Returns an [Error] if data is not a JSON Object, or the issuer,
+    /// authorization_endpoint, or token_endpoint is missing or not a string.
+    fn try_from(data: Value) -> Result<Self, Error> {
+        if !data.is_object() {
+            return Err(Error::new_from(
+                ErrorKind::DiscoveryFailure,
+                String::from("discovery document was not an object"),
+            ));
+        }
+        for field in &["issuer", "authorization_endpoint", "token_endpoint"] {
+            match data.get(field) {
+                Some(val) if val.is_string() => (),
+                _ => {
+                    return Err(Error::new_from(
+                        ErrorKind::DiscoveryFailure,
+                        format!("discovery document {} was missing or not a string", field),
+                    ))
+                }
+            }
+        }
+
+        Ok(Self { data })
+    }
+}
+
+impl DiscoveredProvider {
+    /// Get the discovery document as a raw JSON [Value]. It is guaranteed to
+    /// be of type Object.
+    pub fn as_value(&self) -> &Value {
+        &self.data
+    }
+
+    /// Get the issuer identifier of the provider.
+    pub fn issuer(&self) -> &str {
+        self.data
+            .get("issuer")
+            .and_then(Value::as_str)
+            .expect("issuer required at construction")
+    }
+
+    fn field(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.data
+            .get(name)
+            .and_then(Value::as_str)
+            .map(Cow::Borrowed)
+    }
+}
+
+impl Provider for DiscoveredProvider {
+    fn auth_uri(&self) -> Cow<'_, str> {
+        self.field("authorization_endpoint")
+            .expect("authorization_endpoint required at construction")
+    }
+
+    fn token_uri(&self) -> Cow<'_, str> {
+        self.field("token_endpoint")
+            .expect("token_endpoint required at construction")
+    }
+
+    fn issuer(&self) -> Option<Cow<'_, str>> {
+        self.field("issuer")
+    }
+
+    fn userinfo_uri(&self) -> Option<Cow<'_, str>> {
+        self.field("userinfo_endpoint")
+    }
+
+    fn jwks_uri(&self) -> Option<Cow<'_, str>> {
+        self.field("jwks_uri")
+    }
+
+    fn revocation_uri(&self) -> Option<Cow<'_, str>> {
+        self.field("revocation_endpoint")
+    }
+
+    fn introspection_uri(&self) -> Option<Cow<'_, str>> {
+        self.field("introspection_endpoint")
+    }
+
+    fn device_authorization_uri(&self) -> Option<Cow<'_, str>> {
+        self.field("device_authorization_endpoint")
+    }
+}
+
 macro_rules! providers {
     (@ $(($name:ident $docstr:expr) : $auth:expr, $token:expr),*) => {
         impl StaticProvider {
@@ -70,8 +243,12 @@ providers! {
     Discord: "https://discordapp.com/api/oauth2/authorize", "https://discordapp.com/api/oauth2/token",
     Facebook: "https://www.facebook.com/v3.1/dialog/oauth", "https://graph.facebook.com/v3.1/oauth/access_token",
     GitHub: "https://github.com/login/oauth/authorize", "https://github.com/login/oauth/access_token",
+    GitLab: "https://gitlab.com/oauth/authorize", "https://gitlab.com/oauth/token",
     Google: "https://accounts.google.com/o/oauth2/v2/auth", "https://www.googleapis.com/oauth2/v4/token",
     Microsoft: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize", "https://login.microsoftonline.com/common/oauth2/v2.0/token",
     Reddit: "https://www.reddit.com/api/v1/authorize", "https://www.reddit.com/api/v1/access_token",
+    Spotify: "https://accounts.spotify.com/authorize", "https://accounts.spotify.com/api/token",
+    Strava: "https://www.strava.com/oauth/authorize", "https://www.strava.com/oauth/token",
+    Twitch: "https://id.twitch.tv/oauth2/authorize", "https://id.twitch.tv/oauth2/token",
     Yahoo: "https://api.login.yahoo.com/oauth2/request_auth", "https://api.login.yahoo.com/oauth2/get_token",
 }
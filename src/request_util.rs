@@ -0,0 +1,74 @@
+//! Parameters of authorization and token requests shared by all of the
+//! built-in [`Adapter`](crate::Adapter) implementations, including
+//! `MockAdapter`, which does not make HTTP requests.
+
+use rocket::http::ext::IntoOwned;
+use rocket::http::uri::Absolute;
+use url::Url;
+
+use crate::{Error, ErrorKind, OAuthConfig, TokenRequest};
+
+/// Generate an authorization URI as described by RFC 6749 §4.1.1.
+pub(crate) fn authorization_uri(
+    config: &OAuthConfig,
+    state: &str,
+    scopes: &[&str],
+    extra_params: &[(&str, &str)],
+) -> Result<Absolute<'static>, Error> {
+    let auth_uri = config.provider().auth_uri();
+
+    let mut url = Url::parse(&auth_uri)
+        .map_err(|e| Error::new_from(ErrorKind::InvalidUri(auth_uri.to_string()), e))?;
+    if !is_secure_or_local(&url) {
+        return Err(Error::new_from(
+            ErrorKind::InvalidUri(auth_uri.to_string()),
+            String::from("authorization URI must use https, or http on a loopback host"),
+        ));
+    }
+
+    url.query_pairs_mut()
+        .append_pair("response_type", config.response_type())
+        .append_pair("client_id", config.client_id())
+        .append_pair("redirect_uri", config.redirect_uri())
+        .append_pair("state", state);
+
+    if let Some(scope) = scopes.iter().find(|s| s.contains(char::is_whitespace)) {
+        return Err(Error::new(ErrorKind::InvalidScope(scope.to_string())));
+    }
+    if !scopes.is_empty() || config.include_empty_scope() {
+        url.query_pairs_mut()
+            .append_pair("scope", &scopes.join(config.scope_delimiter()));
+    }
+
+    url.query_pairs_mut().extend_pairs(extra_params);
+
+    Ok(Absolute::parse(url.as_ref())
+        .map_err(|_| Error::new(ErrorKind::InvalidUri(url.to_string())))?
+        .into_owned())
+}
+
+/// Returns whether `url` uses `https`, or `http` on a loopback host such as a
+/// local development server. The user is redirected to the authorization URI,
+/// so anything else is either insecure or a possible open redirect.
+fn is_secure_or_local(url: &Url) -> bool {
+    match (url.scheme(), url.host()) {
+        ("https", Some(_)) => true,
+        ("http", Some(url::Host::Domain(domain))) => domain == "localhost",
+        ("http", Some(url::Host::Ipv4(ip))) => ip.is_loopback(),
+        ("http", Some(url::Host::Ipv6(ip))) => ip.is_loopback(),
+        _ => false,
+    }
+}
+
+/// Get the standard `grant_type` parameter for a token request, before any
+/// override from [`OAuthConfig::grant_type`].
+pub(crate) fn grant_type(token: &TokenRequest) -> &'static str {
+    match token {
+        TokenRequest::AuthorizationCode { .. } => "authorization_code",
+        TokenRequest::RefreshToken(_) => "refresh_token",
+        TokenRequest::DeviceCode(_) => "urn:ietf:params:oauth:grant-type:device_code",
+        TokenRequest::ClientCredentials { .. } => "client_credentials",
+        #[cfg(feature = "deprecated_password_grant")]
+        TokenRequest::Password { .. } => "password",
+    }
+}
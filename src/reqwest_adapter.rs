@@ -0,0 +1,175 @@
+//! [Adapter] implemented using [`reqwest`](https://github.com/seanmonstar/reqwest).
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, DATE, RETRY_AFTER};
+use rocket::http::uri::Absolute;
+use serde_json::Value;
+
+use super::{
+    Adapter, DeviceAuthorization, DiscoveredProvider, Error, ErrorKind, Introspection,
+    NetworkErrorKind, OAuthConfig, TokenRequest, TokenResponse, TokenTypeHint,
+};
+use crate::adapter_util::{self, TokenRequestBody};
+use crate::request_util;
+
+/// `Adapter` implementation that uses a blocking `reqwest` [`Client`] to
+/// perform the token exchange.
+///
+/// The `Client` is provided with [`ReqwestAdapter::with_client`], and its
+/// connection pool, proxy, TLS, and timeout settings are shared with the
+/// rest of the application.
+#[derive(Clone, Debug)]
+pub struct ReqwestAdapter {
+    client: Client,
+    accept: Option<String>,
+}
+
+impl ReqwestAdapter {
+    /// Create a new `ReqwestAdapter` that will use `client` to make requests.
+    ///
+    /// This crate does not enable any of `reqwest`'s TLS features, so one
+    /// such as `rustls-tls` or `native-tls` must be enabled by the
+    /// application for `client` to reach `https` endpoints.
+    ///
+    /// Some providers, such as GitHub, reject requests without a
+    /// `User-Agent`, so `client` should be configured with one. Other
+    /// headers to send with every request can be set with
+    /// `ClientBuilder::default_headers`.
+    pub fn with_client(client: Client) -> ReqwestAdapter {
+        ReqwestAdapter {
+            client,
+            accept: None,
+        }
+    }
+
+    /// Sets the `Accept` header sent with requests to the token endpoint, and
+    /// the revocation, introspection, and device authorization endpoints.
+    /// The default is `application/json`, but some providers require a
+    /// provider-specific value before they respond with JSON.
+    pub fn with_accept(mut self, accept: String) -> ReqwestAdapter {
+        self.accept = Some(accept);
+        self
+    }
+
+    /// Send a form-encoded POST request to `uri`.
+    fn post(&self, uri: &str, req: TokenRequestBody) -> Result<Response, Error> {
+        let mut request = self
+            .client
+            .post(uri)
+            .header(ACCEPT, self.accept.as_deref().unwrap_or("application/json"))
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(authorization) = req.authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+
+        let response = request.body(req.body).send().map_err(|e| {
+            let is_connect = e.is_connect();
+            let error = if e.is_timeout() {
+                Error::new_from(ErrorKind::Timeout, e)
+            } else {
+                Error::new_from(ErrorKind::ExchangeFailure, e)
+            };
+            if is_connect {
+                error.with_network_kind(NetworkErrorKind::Connect)
+            } else {
+                error
+            }
+        })?;
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok());
+        adapter_util::check_rate_limit(response.status().as_u16(), retry_after)?;
+        Ok(response)
+    }
+}
+
+impl Adapter for ReqwestAdapter {
+    fn authorization_uri(
+        &self,
+        config: &OAuthConfig,
+        state: &str,
+        scopes: &[&str],
+        extra_params: &[(&str, &str)],
+    ) -> Result<Absolute<'static>, Error> {
+        request_util::authorization_uri(config, state, scopes, extra_params)
+    }
+
+    fn exchange_code(
+        &self,
+        config: &OAuthConfig,
+        token: TokenRequest,
+    ) -> Result<TokenResponse, Error> {
+        let req = adapter_util::token_request_body(config, token)?;
+        let response = self.post(config.provider().token_uri().as_ref(), req)?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let content_type = header(CONTENT_TYPE);
+        let date = header(DATE);
+        adapter_util::token_response(
+            response.status().as_u16(),
+            content_type.as_deref(),
+            date.as_deref(),
+            response,
+        )
+    }
+
+    fn discover(&self, issuer: &str) -> Result<DiscoveredProvider, Error> {
+        let response = self
+            .client
+            .get(&adapter_util::discovery_uri(issuer))
+            .header(ACCEPT, "application/json")
+            .send()
+            .map_err(|e| Error::new_from(ErrorKind::DiscoveryFailure, e))?;
+
+        adapter_util::discovery_response(issuer, response.status().as_u16(), response)
+    }
+
+    fn fetch_jwks(&self, jwks_uri: &str) -> Result<Value, Error> {
+        let response = self
+            .client
+            .get(jwks_uri)
+            .header(ACCEPT, "application/json")
+            .send()
+            .map_err(|e| Error::new_from(ErrorKind::DiscoveryFailure, e))?;
+
+        adapter_util::jwks_response(response.status().as_u16(), response)
+    }
+
+    fn revoke(
+        &self,
+        config: &OAuthConfig,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), Error> {
+        let (uri, req) = adapter_util::revocation_request_body(config, token, token_type_hint)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::revocation_response(response.status().as_u16(), response)
+    }
+
+    fn introspect(&self, config: &OAuthConfig, token: &str) -> Result<Introspection, Error> {
+        let (uri, req) = adapter_util::introspection_request_body(config, token)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::introspection_response(response.status().as_u16(), response)
+    }
+
+    fn device_authorization(
+        &self,
+        config: &OAuthConfig,
+        scopes: &[&str],
+    ) -> Result<DeviceAuthorization, Error> {
+        let (uri, req) = adapter_util::device_authorization_request_body(config, scopes)?;
+        let response = self.post(&uri, req)?;
+
+        adapter_util::device_authorization_response(response.status().as_u16(), response)
+    }
+}